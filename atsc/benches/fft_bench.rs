@@ -83,7 +83,7 @@ fn fft_decompression_benchmark(c: &mut Criterion) {
 
     c.bench_function("FFT Decompression", |b| {
         b.iter(|| {
-            let decompressed_data = fft_to_data(data.len(), black_box(&compressed_data));
+            let decompressed_data = fft_to_data(data.len(), black_box(&compressed_data)).unwrap();
             black_box(decompressed_data);
         });
     });
@@ -93,7 +93,7 @@ fn fft_decompression_benchmark(c: &mut Criterion) {
 fn fft_initialization_benchmark(c: &mut Criterion) {
     c.bench_function("FFT Initialization", |b| {
         b.iter(|| {
-            let _planner = FFT::new(1024, 0.0, 1.0);
+            let _planner = FFT::<f32>::new(1024, 0.0, 1.0);
         });
     });
 }
@@ -105,7 +105,10 @@ fn decompression_without_fft_benchmark(c: &mut Criterion) {
 
     c.bench_function("Decompression without FFT", |b| {
         b.iter(|| {
-            let decompressed_data = FFT::decompress(&compressed_data).to_data(1024);
+            let decompressed_data = FFT::<f32>::decompress(&compressed_data)
+                .unwrap()
+                .to_data(1024)
+                .unwrap();
             black_box(decompressed_data);
         });
     });
@@ -162,7 +165,10 @@ fn multiple_compression_rounds_benchmark(c: &mut Criterion) {
         b.iter(|| {
             let mut compressed_data = fft(black_box(&data));
             for _ in 1..rounds {
-                compressed_data = fft(&FFT::decompress(&compressed_data).to_data(data.len()));
+                compressed_data = fft(&FFT::<f32>::decompress(&compressed_data)
+                    .unwrap()
+                    .to_data(data.len())
+                    .unwrap());
             }
             black_box(compressed_data);
         });
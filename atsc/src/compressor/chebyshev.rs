@@ -0,0 +1,295 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use super::{BinConfig, CompressorResult};
+use crate::error::Error;
+use crate::utils::{error::error_mape_exact_zeros, round_f64, DECIMAL_PRECISION};
+use bincode::{Decode, Encode};
+use log::{debug, info, trace};
+
+const CHEBYSHEV_COMPRESSOR_ID: u8 = 100;
+/// Highest degree tried for a segment, higher degrees are better served by splitting the segment
+const MAX_DEGREE: usize = 8;
+/// Length of the first segment tried from each starting sample, it doubles from there while the fit holds
+const MIN_SEGMENT: usize = 16;
+/// Error used when no error is provided
+const DEFAULT_ERROR: f64 = 0.01;
+
+/// A segment of samples and the coefficients of the Chebyshev series fitted to them
+#[derive(Encode, Decode, PartialEq, Debug, Clone)]
+pub struct Segment {
+    pub len: u64,
+    pub coefficients: Vec<f64>,
+}
+
+/// Least squares polynomial fit, stored as Chebyshev series.
+///
+/// Each segment of the frame is mapped to [-1, 1] and fitted with the lowest degree series (up to `MAX_DEGREE`)
+/// that is within the error, segments are made as long as a fit holds. Only the coefficients are stored, a smooth
+/// trend (disk fill, memory growth, ...) takes a handful of them for the whole frame.
+/// The Chebyshev basis keeps the least squares system well conditioned, where plain powers of x would not be.
+#[derive(Encode, Decode, PartialEq, Debug, Clone)]
+pub struct Chebyshev {
+    pub id: u8,
+    pub segments: Vec<Segment>,
+}
+
+/// Position of the sample `i` of a segment of `len` samples, in [-1, 1]
+fn position(i: usize, len: usize) -> f64 {
+    if len == 1 {
+        0.0
+    } else {
+        2.0 * i as f64 / (len - 1) as f64 - 1.0
+    }
+}
+
+/// Values of the first `count` Chebyshev polynomials at `x`
+fn basis(x: f64, count: usize) -> Vec<f64> {
+    let mut t = Vec::with_capacity(count);
+    for k in 0..count {
+        t.push(match k {
+            0 => 1.0,
+            1 => x,
+            _ => 2.0 * x * t[k - 1] - t[k - 2],
+        });
+    }
+    t
+}
+
+/// Solves `a * x = b` by Gaussian elimination with partial pivoting, None if the system is singular
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < f64::EPSILON {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..n {
+            let factor = a[row][col] / a[col][col];
+            for k in col..n {
+                a[row][k] -= factor * a[col][k];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Least squares fit of a Chebyshev series of `degree` to `data`
+pub(crate) fn fit(data: &[f64], degree: usize) -> Option<Vec<f64>> {
+    let count = degree + 1;
+    let mut a = vec![vec![0.0; count]; count];
+    let mut b = vec![0.0; count];
+    for (i, value) in data.iter().enumerate() {
+        let t = basis(position(i, data.len()), count);
+        for j in 0..count {
+            b[j] += t[j] * value;
+            for k in 0..count {
+                a[j][k] += t[j] * t[k];
+            }
+        }
+    }
+    solve(a, b)
+}
+
+/// Evaluates the series over the `len` samples of a segment
+pub(crate) fn evaluate(coefficients: &[f64], len: usize) -> Vec<f64> {
+    (0..len)
+        .map(|i| {
+            let t = basis(position(i, len), coefficients.len());
+            let value: f64 = coefficients.iter().zip(t.iter()).map(|(c, t)| c * t).sum();
+            round_f64(value, DECIMAL_PRECISION)
+        })
+        .collect()
+}
+
+/// Coefficients of the lowest degree series within `max_error` of `data`, if there is one
+fn fit_within(data: &[f64], max_error: f64) -> Option<Vec<f64>> {
+    (0..=MAX_DEGREE.min(data.len() - 1)).find_map(|degree| {
+        let coefficients = fit(data, degree)?;
+        let error = error_mape_exact_zeros(data, &evaluate(&coefficients, data.len()));
+        (error <= max_error).then_some(coefficients)
+    })
+}
+
+impl Chebyshev {
+    pub fn new() -> Self {
+        debug!("Chebyshev compressor");
+        Chebyshev {
+            id: CHEBYSHEV_COMPRESSOR_ID,
+            segments: Vec::new(),
+        }
+    }
+
+    /// Splits the data in the longest segments that have a fit within `max_error`
+    pub fn compress_bounded(&mut self, data: &[f64], max_error: f64) {
+        let mut start = 0;
+        while start < data.len() {
+            let remaining = data.len() - start;
+            let fits = |len: usize| fit_within(&data[start..start + len], max_error);
+            // A single sample is always matched by a constant
+            let (mut good, mut coefficients) = (1, vec![data[start]]);
+            let mut bad = None;
+            let mut len = MIN_SEGMENT.min(remaining);
+            // Double the segment while the fit holds, then bisect between the last fit and the first miss
+            while good < remaining {
+                match fits(len) {
+                    Some(c) => {
+                        (good, coefficients) = (len, c);
+                        len = (len * 2).min(remaining);
+                    }
+                    None => {
+                        bad = Some(len);
+                        break;
+                    }
+                }
+            }
+            if let Some(mut bad) = bad {
+                while bad - good > 1 {
+                    let mid = (good + bad) / 2;
+                    match fits(mid) {
+                        Some(c) => (good, coefficients) = (mid, c),
+                        None => bad = mid,
+                    }
+                }
+            }
+            trace!(
+                "Segment at {} of {} samples, degree {}",
+                start,
+                good,
+                coefficients.len() - 1
+            );
+            self.segments.push(Segment {
+                len: good as u64,
+                coefficients,
+            });
+            start += good;
+        }
+        debug!(
+            "Compressed {} elements into {} segments!",
+            data.len(),
+            self.segments.len()
+        );
+    }
+
+    /// Receives a data stream and generates a Chebyshev
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
+        let config = BinConfig::get();
+        let (chebyshev, _) = bincode::decode_from_slice(data, config)?;
+        Ok(chebyshev)
+    }
+
+    /// This function transforms the structure in a Binary stream to be appended to the frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        bincode::encode_to_vec(self, config).unwrap()
+    }
+
+    /// Evaluates the segments back into `frame_size` samples
+    pub fn to_data(&self, frame_size: usize) -> Result<Vec<f64>, Error> {
+        let mut data = Vec::with_capacity(frame_size);
+        for segment in self.segments.iter() {
+            if segment.len > (frame_size - data.len()) as u64 {
+                return Err(Error::CorruptData(format!(
+                    "segments hold more than the {} samples of the frame",
+                    frame_size
+                )));
+            }
+            data.extend(evaluate(&segment.coefficients, segment.len as usize));
+        }
+        Ok(data)
+    }
+}
+
+impl Default for Chebyshev {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compresses the data with the default error
+pub fn chebyshev(data: &[f64]) -> Vec<u8> {
+    info!("Initializing Chebyshev Compressor");
+    let mut c = Chebyshev::new();
+    c.compress_bounded(data, DEFAULT_ERROR);
+    c.to_bytes()
+}
+
+/// Compresses the data with segments that are each within `max_error`
+pub fn chebyshev_allowed_error(data: &[f64], max_error: f64) -> CompressorResult {
+    info!(
+        "Initializing Chebyshev Compressor. Max error: {}",
+        max_error
+    );
+    let mut c = Chebyshev::new();
+    c.compress_bounded(data, max_error);
+    let decompressed = c.to_data(data.len()).unwrap();
+    CompressorResult::new(c.to_bytes(), error_mape_exact_zeros(data, &decompressed))
+}
+
+pub fn chebyshev_to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    let c = Chebyshev::decompress(compressed_data)?;
+    c.to_data(sample_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chebyshev_trend() {
+        // Memory growing on a curve, a single segment of degree 2 holds it exactly
+        let vector1: Vec<f64> = (0..4096)
+            .map(|i| 1000.0 + i as f64 * 3.0 + (i * i) as f64 * 0.5)
+            .collect();
+        let mut c = Chebyshev::new();
+        c.compress_bounded(&vector1, 0.001);
+        assert_eq!(c.segments.len(), 1);
+        assert_eq!(c.segments[0].coefficients.len(), 3);
+        let out = chebyshev_to_data(vector1.len(), &c.to_bytes()).unwrap();
+        for (original, decompressed) in vector1.iter().zip(out.iter()) {
+            assert!((original - decompressed).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_chebyshev_allowed_error() {
+        let vector1: Vec<f64> = (0..3000)
+            .map(|i| 500.0 + (i as f64 / 300.0).sin() * 100.0 + ((i * 7919) % 13) as f64)
+            .chain([0.0, 0.0, 5.0])
+            .collect();
+        let result = chebyshev_allowed_error(&vector1, 0.02);
+        assert!(result.error <= 0.02);
+        assert!(result.compressed_data.len() < 3003 / 4);
+        let out = chebyshev_to_data(vector1.len(), &result.compressed_data).unwrap();
+        assert_eq!(error_mape_exact_zeros(&vector1, &out), result.error);
+    }
+
+    #[test]
+    fn test_corrupted_segments() {
+        let vector1: Vec<f64> = (0..100).map(|i| i as f64).collect();
+        let compressed = chebyshev(&vector1);
+        assert!(chebyshev_to_data(50, &compressed).is_err());
+        assert!(chebyshev_to_data(100, &compressed[..compressed.len() - 1]).is_err());
+    }
+}
@@ -16,6 +16,7 @@ limitations under the License.
 
 use crate::{
     compressor::CompressorResult,
+    error::Error,
     optimizer::utils::{Bitdepth, DataStats},
 };
 
@@ -112,10 +113,10 @@ impl Constant {
     }
 
     /// Receives a data stream and generates a Constant
-    pub fn decompress(data: &[u8]) -> Self {
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
         let config = BinConfig::get();
-        let (ct, _) = bincode::decode_from_slice(data, config).unwrap();
-        ct
+        let (ct, _) = bincode::decode_from_slice(data, config)?;
+        Ok(ct)
     }
 
     /// This function transforms the structure into a Binary stream
@@ -138,9 +139,9 @@ pub fn constant_compressor(data: &[f64], stats: DataStats) -> CompressorResult {
     CompressorResult::new(c.to_bytes(), 0.0)
 }
 
-pub fn constant_to_data(sample_number: usize, compressed_data: &[u8]) -> Vec<f64> {
-    let c = Constant::decompress(compressed_data);
-    c.to_data(sample_number)
+pub fn constant_to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    let c = Constant::decompress(compressed_data)?;
+    Ok(c.to_data(sample_number))
 }
 
 #[cfg(test)]
@@ -172,8 +173,16 @@ mod tests {
         let vector1 = vec![1.0, 1.0, 1.0, 1.0, 1.0];
         let stats = DataStats::new(&vector1);
         let c = Constant::new(vector1.len(), stats.min, stats.bitdepth).to_bytes();
-        let c2 = constant_to_data(vector1.len(), &c);
+        let c2 = constant_to_data(vector1.len(), &c).unwrap();
 
         assert_eq!(vector1, c2);
     }
+
+    #[test]
+    fn test_truncated_data() {
+        assert!(matches!(
+            constant_to_data(5, &[30]),
+            Err(Error::TruncatedInput)
+        ));
+    }
 }
@@ -0,0 +1,312 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use super::{BinConfig, CompressorResult};
+use crate::error::Error;
+use crate::utils::bits::{unzigzag, zigzag, BitReader, BitWriter};
+use crate::utils::error::error_mape_exact_zeros;
+use crate::utils::DECIMAL_PRECISION;
+use bincode::{Decode, Encode};
+use log::{debug, info};
+
+const COUNTER_COMPRESSOR_ID: u8 = 70;
+/// Largest integer a f64 holds exactly
+pub(crate) const MAX_EXACT_INT: f64 = (1u64 << f64::MANTISSA_DIGITS) as f64;
+/// Buckets for the delta of deltas, (prefix bits, prefix length, value bits). A delta of deltas of 0 is a single 0 bit.
+const BUCKETS: [(u64, u32, u32); 4] = [
+    (0b10, 2, 8),
+    (0b110, 3, 16),
+    (0b1110, 4, 32),
+    (0b1111, 4, 64),
+];
+
+/// Compressor for monotonic counters, the ones that only go up except when they reset on a process restart.
+///
+/// Values are scaled to integers by the smallest power of 10 that keeps them exact (up to `DECIMAL_PRECISION`
+/// decimals). Each sample is then stored as the difference between its delta and the previous delta, zigzag
+/// encoded into a variable length bucket, a counter growing at a steady rate takes a bit per sample.
+/// Any sample lower than the previous one is a reset, its position and value are stored apart and the delta of
+/// deltas carries on from the delta before the reset, so the decoded series is exactly the original one.
+#[derive(Encode, Decode, PartialEq, Debug, Clone)]
+pub struct Counter {
+    pub id: u8,
+    /// Values are stored multiplied by 10^decimals
+    pub decimals: u8,
+    pub first: i64,
+    /// Resets as (samples since the previous reset, value after the reset)
+    pub resets: Vec<(u64, i64)>,
+    pub data: Vec<u8>,
+}
+
+/// Finds the smallest number of decimals that represents every value exactly, `DECIMAL_PRECISION` if there is none
+pub(crate) fn find_decimals(data: &[f64]) -> u32 {
+    (0..DECIMAL_PRECISION)
+        .find(|&decimals| {
+            let scale = 10f64.powi(decimals as i32);
+            data.iter().all(|&v| {
+                let scaled = (v * scale).round();
+                scaled.abs() < MAX_EXACT_INT && scaled / scale == v
+            })
+        })
+        .unwrap_or(DECIMAL_PRECISION)
+}
+
+/// Decimals the counter keeps: the ones from `find_decimals` when they are exact, otherwise as many as fit in the
+/// integers a f64 holds exactly. `None` if not even the integer part of the values fits.
+pub(crate) fn counter_decimals(data: &[f64]) -> Option<u32> {
+    (0..=find_decimals(data)).rev().find(|&decimals| {
+        let scale = 10f64.powi(decimals as i32);
+        data.iter()
+            .all(|&v| (v * scale).round().abs() < MAX_EXACT_INT)
+    })
+}
+
+/// Checks if the data looks like a counter: it grows, and it only goes down on the odd reset
+pub fn is_counter(data: &[f64]) -> bool {
+    let (mut ups, mut downs) = (0, 0);
+    for w in data.windows(2) {
+        if w[1] > w[0] {
+            ups += 1;
+        } else if w[1] < w[0] {
+            downs += 1;
+        }
+    }
+    ups > 0 && downs * 100 <= data.len()
+}
+
+impl Counter {
+    pub fn new() -> Self {
+        debug!("Counter compressor");
+        Counter {
+            id: COUNTER_COMPRESSOR_ID,
+            decimals: 0,
+            first: 0,
+            resets: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    /// Fails with `Error::OutOfRange` if the values are too large to scale to exact integers
+    pub fn compress(&mut self, data: &[f64]) -> Result<(), Error> {
+        let decimals = counter_decimals(data).ok_or_else(|| {
+            Error::OutOfRange(format!(
+                "counter values must stay below {MAX_EXACT_INT} in magnitude"
+            ))
+        })?;
+        let scale = 10f64.powi(decimals as i32);
+        let values: Vec<i64> = data.iter().map(|v| (v * scale).round() as i64).collect();
+        self.decimals = decimals as u8;
+        self.first = values.first().copied().unwrap_or_default();
+        let mut writer = BitWriter::new();
+        let mut previous_delta: i64 = 0;
+        let mut last_reset = 0;
+        for (i, w) in values.windows(2).enumerate() {
+            if w[1] < w[0] {
+                self.resets.push(((i + 1 - last_reset) as u64, w[1]));
+                last_reset = i + 1;
+                continue;
+            }
+            let delta = w[1].wrapping_sub(w[0]);
+            let dod = zigzag(delta.wrapping_sub(previous_delta));
+            previous_delta = delta;
+            if dod == 0 {
+                writer.write_bit(false);
+                continue;
+            }
+            let (prefix, prefix_len, bits) = BUCKETS
+                .into_iter()
+                .find(|&(_, _, bits)| bits == 64 || dod < 1 << bits)
+                .unwrap();
+            writer.write_bits(prefix, prefix_len);
+            writer.write_bits(dod, bits);
+        }
+        self.data = writer.finish();
+        debug!(
+            "Compressed {} elements into {} bytes, {} resets, {} decimals",
+            data.len(),
+            self.data.len(),
+            self.resets.len(),
+            self.decimals
+        );
+        Ok(())
+    }
+
+    /// Receives a data stream and generates a Counter
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
+        let config = BinConfig::get();
+        let (counter, _) = bincode::decode_from_slice(data, config)?;
+        Ok(counter)
+    }
+
+    /// This function transforms the structure in a Binary stream to be appended to the frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        bincode::encode_to_vec(self, config).unwrap()
+    }
+
+    /// Rebuilds the `frame_size` values out of the delta of deltas and the resets
+    pub fn to_data(&self, frame_size: usize) -> Result<Vec<f64>, Error> {
+        if self.decimals as u32 > DECIMAL_PRECISION {
+            return Err(Error::CorruptData(format!(
+                "counter stored with {} decimals",
+                self.decimals
+            )));
+        }
+        let scale = 10f64.powi(self.decimals as i32);
+        let mut out =
+            Vec::with_capacity(frame_size.min(self.data.len() * 8 + self.resets.len() + 1));
+        if frame_size == 0 {
+            return Ok(out);
+        }
+        let mut reader = BitReader::new(&self.data);
+        let mut resets = self.resets.iter();
+        let mut next_reset = resets.next().map(|&(gap, value)| (gap as usize, value));
+        let mut value = self.first;
+        let mut previous_delta: i64 = 0;
+        out.push(value as f64 / scale);
+        while out.len() < frame_size {
+            let i = out.len();
+            match next_reset {
+                Some((reset, reset_value)) if reset == i => {
+                    value = reset_value;
+                    next_reset = resets
+                        .next()
+                        .map(|&(gap, value)| (reset.saturating_add(gap as usize), value));
+                }
+                _ => {
+                    let mut dod = 0;
+                    if reader.read_bit()? {
+                        let mut prefix_len = 1;
+                        while prefix_len < 4 && reader.read_bit()? {
+                            prefix_len += 1;
+                        }
+                        let (_, _, bits) = BUCKETS[prefix_len - 1];
+                        dod = reader.read_bits(bits)?;
+                    }
+                    previous_delta = previous_delta.wrapping_add(unzigzag(dod));
+                    value = value.wrapping_add(previous_delta);
+                }
+            }
+            out.push(value as f64 / scale);
+        }
+        Ok(out)
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn counter(data: &[f64]) -> Result<Vec<u8>, Error> {
+    info!("Initializing Counter Compressor");
+    let mut c = Counter::new();
+    c.compress(data)?;
+    Ok(c.to_bytes())
+}
+
+/// Compresses the data and calculates the error out of the decoded values, values with more decimals than the
+/// counter keeps lose precision
+pub fn counter_compressor(data: &[f64]) -> Result<CompressorResult, Error> {
+    let mut c = Counter::new();
+    c.compress(data)?;
+    let decompressed = c.to_data(data.len())?;
+    Ok(CompressorResult::new(
+        c.to_bytes(),
+        error_mape_exact_zeros(data, &decompressed),
+    ))
+}
+
+pub fn counter_to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    let c = Counter::decompress(compressed_data)?;
+    c.to_data(sample_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steady_counter() {
+        // First delta in an 8 bits bucket, then a single bit per sample
+        let vector1: Vec<f64> = (0..16).map(|i| 100.0 + i as f64 * 5.0).collect();
+        let compressed = counter(&vector1).unwrap();
+        assert_eq!(compressed, [70, 0, 200, 0, 3, 130, 128, 0]);
+        assert_eq!(
+            counter_to_data(vector1.len(), &compressed).unwrap(),
+            vector1
+        );
+    }
+
+    #[test]
+    fn test_counter_resets() {
+        let mut vector1: Vec<f64> = (0..300).map(|i| (i * i) as f64 * 0.25).collect();
+        vector1.extend((0..200).map(|i| i as f64 * 1.5));
+        vector1.extend([0.0, 0.0, 12.75, 3.0, 3.5]);
+        let mut c = Counter::new();
+        c.compress(&vector1).unwrap();
+        assert_eq!(c.decimals, 2);
+        assert_eq!(c.resets, [(300, 0), (200, 0), (3, 300)]);
+        let decompressed = counter_to_data(vector1.len(), &c.to_bytes()).unwrap();
+        assert_eq!(decompressed, vector1);
+        // Never goes backwards where the original doesn't
+        assert!(decompressed[..300].windows(2).all(|w| w[1] >= w[0]));
+    }
+
+    #[test]
+    fn test_is_counter() {
+        let vector1: Vec<f64> = (0..500).map(|i| (i % 250) as f64).collect();
+        assert!(is_counter(&vector1));
+        assert!(!is_counter(&[1.0, 1.0, 1.0]));
+        assert!(!is_counter(&[1.0, 2.0, 1.0, 2.0, 1.0]));
+    }
+
+    #[test]
+    fn test_counter_precision() {
+        let vector1 = [0.1234567, 0.2345678, 10.0];
+        let result = counter_compressor(&vector1).unwrap();
+        assert!(result.error > 0.0 && result.error < 0.0001);
+        let vector2 = [0.5, 1.25, 2.0];
+        assert_eq!(counter_compressor(&vector2).unwrap().error, 0.0);
+        // Too large to keep 5 decimals, they are kept as far as they fit and the error says so
+        let vector3: Vec<f64> = (0..10).map(|i| 1e12 + i as f64 * 0.123456).collect();
+        let result = counter_compressor(&vector3).unwrap();
+        assert!(result.error > 0.0 && result.error < 1e-12);
+    }
+
+    #[test]
+    fn test_counter_out_of_range() {
+        // Scaling these would saturate the i64 values
+        for vector1 in [[1e16, 2e16], [1e300, 2e300], [-1e16, 0.0]] {
+            assert!(counter(&vector1).is_err());
+            assert!(counter_compressor(&vector1).is_err());
+            assert_eq!(counter_decimals(&vector1), None);
+        }
+        let vector2 = [9e15, 9e15 + 1.0];
+        assert_eq!(counter_decimals(&vector2), Some(0));
+        let compressed = counter(&vector2).unwrap();
+        assert_eq!(counter_to_data(2, &compressed).unwrap(), vector2);
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        let vector1 = vec![1.0, 2.0, 30.0];
+        let compressed = counter(&vector1).unwrap();
+        assert!(counter_to_data(vector1.len() + 200, &compressed).is_err());
+        assert!(counter_to_data(vector1.len(), &compressed[..compressed.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,283 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use super::fft::{bounded_search, FrequencyPoint, FFT};
+use super::{BinConfig, CompressorResult};
+use crate::{
+    error::Error,
+    optimizer::utils::DataStats,
+    utils::{error::calculate_error, DECIMAL_PRECISION},
+};
+use bincode::{Decode, Encode};
+use log::{debug, info};
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::f64::consts::PI;
+
+const DCT_COMPRESSOR_ID: u8 = 20;
+
+/// DCT Compressor. Applies a DCT-II to a signal, picks the N biggest coefficients, discards the rest. Always LOSSY
+///
+/// The DCT sees the frame mirrored at the edges instead of repeated, so there is no jump between the last and the
+/// first sample to spread over every frequency, and no padding is needed to fight the Gibbs phenomenon like in the
+/// FFT compressor. Coefficients are the amplitude of each cosine, kept in pairs so no space is wasted: the
+/// `FrequencyPoint` at position `p` holds the coefficient `2p` in the real part and `2p + 1` in the imaginary part.
+#[derive(PartialEq, Debug)]
+pub struct Dct {
+    pub id: u8,
+    pub frequencies: Vec<FrequencyPoint>,
+    pub max_value: f32,
+    pub min_value: f32,
+    pub error: Option<f64>,
+}
+
+// Implementing the Encode manually because we don't want to encode the Error field, less bytes used.
+impl Encode for Dct {
+    fn encode<__E: ::bincode::enc::Encoder>(
+        &self,
+        encoder: &mut __E,
+    ) -> Result<(), ::bincode::error::EncodeError> {
+        Encode::encode(&self.id, encoder)?;
+        Encode::encode(&self.frequencies, encoder)?;
+        Encode::encode(&self.max_value, encoder)?;
+        Encode::encode(&self.min_value, encoder)?;
+        Ok(())
+    }
+}
+
+impl Decode for Dct {
+    fn decode<__D: ::bincode::de::Decoder>(
+        decoder: &mut __D,
+    ) -> Result<Self, ::bincode::error::DecodeError> {
+        Ok(Self {
+            id: Decode::decode(decoder)?,
+            frequencies: Decode::decode(decoder)?,
+            max_value: Decode::decode(decoder)?,
+            min_value: Decode::decode(decoder)?,
+            error: None,
+        })
+    }
+}
+
+bincode::impl_borrow_decode!(Dct);
+
+/// Forward DCT-II, computed with a FFT of the frame followed by its mirror.
+/// Returns the amplitude of each cosine, so `data[n] = sum(c[k] * cos(PI * k * (n + 0.5) / N))`
+fn dct2(data: &[f64]) -> Vec<f64> {
+    let len = data.len();
+    let mut buffer: Vec<Complex<f64>> = data
+        .iter()
+        .chain(data.iter().rev())
+        .map(|&x| Complex { re: x, im: 0.0 })
+        .collect();
+    let mut planner = FftPlanner::new();
+    planner.plan_fft_forward(2 * len).process(&mut buffer);
+    buffer
+        .iter()
+        .take(len)
+        .enumerate()
+        .map(|(k, f)| {
+            let twiddle = Complex::from_polar(1.0, -PI * k as f64 / (2 * len) as f64);
+            let scale = if k == 0 { 0.5 } else { 1.0 };
+            (f * twiddle).re * scale / len as f64
+        })
+        .collect()
+}
+
+/// Inverse of `dct2` (a DCT-III), out of the stored pairs of coefficients
+fn dct3(frequencies: &[FrequencyPoint], len: usize) -> Vec<f64> {
+    let mut coefficients = vec![0.0; len];
+    for f in frequencies {
+        let pair = f.to_complex();
+        let pos = 2 * f.pos();
+        coefficients[pos] = pair.re as f64;
+        if pos + 1 < len {
+            coefficients[pos + 1] = pair.im as f64;
+        }
+    }
+    let mut buffer = vec![Complex { re: 0.0, im: 0.0 }; 2 * len];
+    buffer[0].re = coefficients[0];
+    for (k, &amplitude) in coefficients.iter().enumerate().skip(1) {
+        let value = Complex::from_polar(amplitude / 2.0, PI * k as f64 / (2 * len) as f64);
+        buffer[k] = value;
+        buffer[2 * len - k] = value.conj();
+    }
+    let mut planner = FftPlanner::new();
+    planner.plan_fft_inverse(2 * len).process(&mut buffer);
+    buffer.iter().take(len).map(|f| f.re).collect()
+}
+
+impl Dct {
+    pub fn new(sample_count: usize, min: f64, max: f64) -> Self {
+        debug!("DCT compressor: min:{} max:{}", min, max);
+        Dct {
+            id: DCT_COMPRESSOR_ID,
+            frequencies: Vec::with_capacity(sample_count),
+            max_value: max as f32,
+            min_value: min as f32,
+            error: None,
+        }
+    }
+
+    /// Rounds a number to `DECIMAL_PRECISION` decimal places, within the frame limits
+    fn round(&self, x: f64) -> f64 {
+        let y = 10i32.pow(DECIMAL_PRECISION) as f64;
+        let out = (x * y).round() / y;
+        out.clamp(self.min_value as f64, self.max_value as f64)
+    }
+
+    /// Keeps the `max_freq` biggest pairs of coefficients
+    fn trim(coefficients: &[f64], max_freq: usize) -> Vec<FrequencyPoint> {
+        let mut buffer: Vec<Complex<f32>> = coefficients
+            .chunks(2)
+            .map(|pair| Complex {
+                re: pair[0] as f32,
+                im: pair.get(1).copied().unwrap_or_default() as f32,
+            })
+            .collect();
+        FFT::fft_trim(&mut buffer, max_freq)
+    }
+
+    /// Compresses data via DCT
+    /// The set of pairs of coefficients to store is 1/100 of the data length OR 3, which is bigger.
+    pub fn compress(&mut self, data: &[f64]) {
+        if self.max_value == self.min_value {
+            debug!("Same max and min, we're done here!");
+            return;
+        }
+        let max_freq = 3.max(data.len() / 100);
+        self.frequencies = Dct::trim(&dct2(data), max_freq);
+    }
+
+    /// Compresses data via DCT - EXPENSIVE
+    /// Same search as `FFT::compress_bounded`, adds coefficients until the error is within `max_err`.
+    pub fn compress_bounded(&mut self, data: &[f64], max_err: f64) {
+        if self.max_value == self.min_value {
+            debug!("Same max and min, we're done here!");
+            return;
+        }
+        let max_freq = 3.max(data.len() / 100);
+        let coefficients = dct2(data);
+        let current_err = bounded_search(max_freq, max_err, |freqs| {
+            self.frequencies = Dct::trim(&coefficients, freqs);
+            let out_data: Vec<f64> = dct3(&self.frequencies, data.len())
+                .into_iter()
+                .map(|x| self.round(x))
+                .collect();
+            calculate_error(data, &out_data)
+        });
+        self.error = Some(current_err);
+        debug!(
+            "Coefficients: {}, Error: {}",
+            self.frequencies.len(),
+            current_err
+        );
+    }
+
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
+        let config = BinConfig::get();
+        let (dct, _) = bincode::decode_from_slice(data, config)?;
+        Ok(dct)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        bincode::encode_to_vec(self, config).unwrap()
+    }
+
+    /// Returns an array of data, runs the inverse DCT over the stored coefficients
+    pub fn to_data(&self, frame_size: usize) -> Result<Vec<f64>, Error> {
+        if self.max_value == self.min_value {
+            debug!("Same max and min, faster decompression!");
+            return Ok(vec![self.max_value as f64; frame_size]);
+        }
+        if self.frequencies.iter().any(|f| 2 * f.pos() >= frame_size) {
+            return Err(Error::CorruptData(
+                "DCT coefficient out of the frame range".to_string(),
+            ));
+        }
+        Ok(dct3(&self.frequencies, frame_size)
+            .into_iter()
+            .map(|x| self.round(x))
+            .collect())
+    }
+}
+
+/// Compresses a data segment via DCT.
+pub fn dct(data: &[f64]) -> Vec<u8> {
+    info!("Initializing DCT Compressor");
+    let stats = DataStats::new(data);
+    let mut c = Dct::new(data.len(), stats.min, stats.max);
+    c.compress(data);
+    c.to_bytes()
+}
+
+/// Compress targeting a specific max error allowed. Like the FFT, the inverse DCT will be calculated over and
+/// over until the specific error threshold is achived.
+pub fn dct_compressor(data: &[f64], allowed_error: f64, stats: DataStats) -> CompressorResult {
+    debug!("Initializing DCT Compressor. Error and Stats provided");
+    let mut c = Dct::new(data.len(), stats.min, stats.max);
+    c.compress_bounded(data, allowed_error);
+    CompressorResult::new(c.to_bytes(), c.error.unwrap_or(0.0))
+}
+
+/// Uncompress a DCT data
+pub fn dct_to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    let c = Dct::decompress(compressed_data)?;
+    c.to_data(sample_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::fft::fft_compressor;
+
+    #[test]
+    fn test_dct_roundtrip() {
+        let vector1 = vec![1.0, 1.0, 1.0, 1.0, 2.0, 1.0, 1.0, 1.0, 3.0, 1.0, 1.0, 5.0];
+        let coefficients = dct2(&vector1);
+        // The first coefficient is the mean
+        assert!((coefficients[0] - 19.0 / 12.0).abs() < 1e-9);
+        let mut c = Dct::new(vector1.len(), 1.0, 5.0);
+        c.frequencies = Dct::trim(&coefficients, vector1.len());
+        let out = dct_to_data(vector1.len(), &c.to_bytes()).unwrap();
+        assert_eq!(out, vector1);
+    }
+
+    #[test]
+    fn test_dct_allowed_error() {
+        let vector1: Vec<f64> = (0..1000)
+            .map(|i| 100.0 + (i as f64 / 30.0).sin() * 10.0 + i as f64 * 0.2)
+            .collect();
+        let stats = DataStats::new(&vector1);
+        let result = dct_compressor(&vector1, 0.01, stats);
+        let out = dct_to_data(vector1.len(), &result.compressed_data).unwrap();
+        assert!(calculate_error(&vector1, &out) <= 0.01);
+        assert_eq!(calculate_error(&vector1, &out), result.error);
+        // A trend is not periodic, the FFT needs more frequencies for the same error
+        let fft_result = fft_compressor(&vector1, 0.01, DataStats::new(&vector1));
+        assert!(result.compressed_data.len() < fft_result.compressed_data.len());
+    }
+
+    #[test]
+    fn test_dct_constant_and_corrupted() {
+        let vector1 = vec![7.0; 100];
+        let compressed = dct(&vector1);
+        assert_eq!(dct_to_data(vector1.len(), &compressed).unwrap(), vector1);
+        let vector2: Vec<f64> = (0..100).map(|i| i as f64).collect();
+        let compressed = dct(&vector2);
+        assert!(dct_to_data(2, &compressed).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use super::counter::MAX_EXACT_INT;
+use super::{BinConfig, CompressorResult};
+use crate::error::Error;
+use crate::optimizer::utils::DataStats;
+use crate::utils::bits::{bit_width, unzigzag, zigzag, BitReader, BitWriter};
+use crate::utils::error::calculate_error;
+use bincode::{Decode, Encode};
+use log::{debug, info};
+
+const DELTA_COMPRESSOR_ID: u8 = 60;
+/// Deltas are packed in blocks of this size, each with its own reference and bit width
+const BLOCK_SIZE: usize = 128;
+/// Bits needed to store a bit width (0 to 64)
+const WIDTH_BITS: u32 = 7;
+
+/// Compressor for integer series (counters, gauges of integer values).
+///
+/// The first value is stored as is, every other one as the delta from the previous value. Deltas are zigzag
+/// encoded, so small negative deltas stay small, and bit-packed in blocks (frame of reference): each block stores
+/// its smallest zigzag delta and then every delta minus that reference, with the bits needed by the largest one.
+/// A counter growing at a steady rate ends up with a few bits per sample, or none at all.
+/// Like `Noop`, values are rounded to integers, so it is lossless only for non fractional data.
+#[derive(Encode, Decode, PartialEq, Debug, Clone)]
+pub struct Delta {
+    pub id: u8,
+    pub first: i64,
+    pub data: Vec<u8>,
+}
+
+/// Checks if every value comes back as is from its integer, the ones too large for a f64 to hold every integer and
+/// negative zeros don't
+pub(crate) fn fits_delta(data: &[f64]) -> bool {
+    data.iter()
+        .all(|v| v.abs() < MAX_EXACT_INT && !(*v == 0.0 && v.is_sign_negative()))
+}
+
+impl Delta {
+    pub fn new() -> Self {
+        debug!("Delta compressor");
+        Delta {
+            id: DELTA_COMPRESSOR_ID,
+            first: 0,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn compress(&mut self, data: &[f64]) {
+        let values: Vec<i64> = data.iter().map(|v| v.round() as i64).collect();
+        self.first = values.first().copied().unwrap_or_default();
+        let deltas: Vec<u64> = values
+            .windows(2)
+            .map(|w| zigzag(w[1].wrapping_sub(w[0])))
+            .collect();
+        let mut writer = BitWriter::new();
+        for block in deltas.chunks(BLOCK_SIZE) {
+            let reference = *block.iter().min().unwrap();
+            let width = block
+                .iter()
+                .map(|d| bit_width(d - reference))
+                .max()
+                .unwrap();
+            writer.write_bits(bit_width(reference) as u64, WIDTH_BITS);
+            writer.write_bits(reference, bit_width(reference));
+            writer.write_bits(width as u64, WIDTH_BITS);
+            for delta in block {
+                writer.write_bits(delta - reference, width);
+            }
+        }
+        self.data = writer.finish();
+        debug!(
+            "Compressed {} elements into {} bytes!",
+            data.len(),
+            self.data.len()
+        );
+    }
+
+    /// Receives a data stream and generates a Delta
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
+        let config = BinConfig::get();
+        let (delta, _) = bincode::decode_from_slice(data, config)?;
+        Ok(delta)
+    }
+
+    /// This function transforms the structure in a Binary stream to be appended to the frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        bincode::encode_to_vec(self, config).unwrap()
+    }
+
+    /// Rebuilds the `frame_size` values out of the packed deltas
+    pub fn to_data(&self, frame_size: usize) -> Result<Vec<f64>, Error> {
+        let mut out = Vec::with_capacity(frame_size.min(BLOCK_SIZE * (self.data.len() + 1)));
+        if frame_size == 0 {
+            return Ok(out);
+        }
+        let mut reader = BitReader::new(&self.data);
+        let read_width = |reader: &mut BitReader| -> Result<u32, Error> {
+            let width = reader.read_bits(WIDTH_BITS)? as u32;
+            if width > 64 {
+                return Err(Error::CorruptData(format!(
+                    "delta block with {} bits per value",
+                    width
+                )));
+            }
+            Ok(width)
+        };
+        let mut value = self.first;
+        out.push(value as f64);
+        while out.len() < frame_size {
+            let reference_width = read_width(&mut reader)?;
+            let reference = reader.read_bits(reference_width)?;
+            let width = read_width(&mut reader)?;
+            let block_len = BLOCK_SIZE.min(frame_size - out.len());
+            for _ in 0..block_len {
+                let delta = reader.read_bits(width)?.wrapping_add(reference);
+                value = value.wrapping_add(unzigzag(delta));
+                out.push(value as f64);
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl Default for Delta {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn delta(data: &[f64]) -> Vec<u8> {
+    info!("Initializing Delta Compressor");
+    let mut c = Delta::new();
+    c.compress(data);
+    c.to_bytes()
+}
+
+/// Compresses the data and calculates the error, out of the decoded values unless they are known to be exact
+pub fn delta_compressor(data: &[f64], stats: DataStats) -> CompressorResult {
+    let mut c = Delta::new();
+    c.compress(data);
+    let compressed = c.to_bytes();
+    if !stats.fractional && fits_delta(data) {
+        return CompressorResult::new(compressed, 0.0);
+    }
+    let decompressed = c.to_data(data.len()).unwrap();
+    CompressorResult::new(compressed, calculate_error(data, &decompressed))
+}
+
+pub fn delta_to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    let c = Delta::decompress(compressed_data)?;
+    c.to_data(sample_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta_counter() {
+        // Steady counter, every delta is the same so no bits are needed for them
+        let vector1: Vec<f64> = (0..256).map(|i| 1000.0 + i as f64 * 10.0).collect();
+        let compressed = delta(&vector1);
+        assert_eq!(compressed, [60, 251, 208, 7, 5, 11, 64, 1, 104, 0]);
+        assert_eq!(delta_to_data(vector1.len(), &compressed).unwrap(), vector1);
+    }
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let vector1: Vec<f64> = (0..1000)
+            .map(|i| ((i * 7919) % 1013) as f64 - 500.0)
+            .chain([i64::MAX as f64, i64::MIN as f64, 0.0, 3.0])
+            .collect();
+        let compressed = delta(&vector1);
+        assert_eq!(delta_to_data(vector1.len(), &compressed).unwrap(), vector1);
+        assert_eq!(delta_to_data(1, &delta(&[42.0])).unwrap(), [42.0]);
+    }
+
+    #[test]
+    fn test_delta_exactness() {
+        // Beyond the i64 range the values saturate, the error has to say so
+        let vector1: Vec<f64> = (0..100).map(|i| 1e19 + i as f64 * 4096.0).collect();
+        assert!(!fits_delta(&vector1));
+        assert!(delta_compressor(&vector1, DataStats::new(&vector1)).error > 0.0);
+        let vector2: Vec<f64> = vector1.iter().map(|v| -v).collect();
+        assert!(delta_compressor(&vector2, DataStats::new(&vector2)).error > 0.0);
+        // A negative zero comes back as a positive one
+        assert!(!fits_delta(&[1.0, -0.0, 2.0]));
+        assert!(fits_delta(&[1.0, 0.0, -2.0, 9e15]));
+        let vector3 = [1.0, 5.0, 2.0];
+        assert_eq!(
+            delta_compressor(&vector3, DataStats::new(&vector3)).error,
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        let vector1 = vec![1.0, 2.0, 30.0];
+        let compressed = delta(&vector1);
+        assert!(delta_to_data(vector1.len() + 200, &compressed).is_err());
+        assert!(delta_to_data(vector1.len(), &compressed[..compressed.len() - 1]).is_err());
+    }
+}
@@ -15,31 +15,99 @@ limitations under the License.
 */
 
 use crate::{
+    error::Error,
     optimizer::utils::DataStats,
-    utils::{error::calculate_error, next_size},
+    utils::{
+        bits::{bit_width, unzigzag, zigzag, BitReader, BitWriter},
+        error::calculate_error,
+        next_size,
+    },
 };
-use bincode::{Decode, Encode};
-use rustfft::{num_complex::Complex, FftPlanner};
+use bincode::{error::DecodeError, Decode, Encode};
+use num_traits::Float;
+use rustfft::{num_complex::Complex, FftNum, FftPlanner};
 use std::{cmp::Ordering, collections::BinaryHeap};
 
 use super::{BinConfig, CompressorResult};
 use log::{debug, error, info, trace, warn};
 
 const FFT_COMPRESSOR_ID: u8 = 15;
+/// Written instead of `FFT_COMPRESSOR_ID` when the frequencies are quantized, payloads with f32 frequencies keep
+/// decoding as before
+const FFT_QUANTIZED_ID: u8 = 16;
+/// Same as `FFT_COMPRESSOR_ID` and `FFT_QUANTIZED_ID`, for the f64 pipeline
+const FFT_F64_ID: u8 = 17;
+const FFT_F64_QUANTIZED_ID: u8 = 18;
+/// Integers up to this one are exact in a f32
+const F32_EXACT_INT: f64 = 16_777_216.0;
+/// Share of the error budget that the quantization of the frequencies can take
+const QUANTIZATION_SHARE: f64 = 0.25;
 const DECIMAL_PRECISION: u8 = 5;
 
+/// Floating point type the FFT runs on. Each one is written with its own ids, so the payload tells how to decode it
+pub trait FftFloat: FftNum + Float + Encode + Decode {
+    /// Id of the payloads with the frequencies as they are
+    const RAW_ID: u8;
+    /// Id of the payloads with quantized frequencies
+    const QUANTIZED_ID: u8;
+    /// Converts with `as`, values out of range become infinite
+    fn of_f64(x: f64) -> Self;
+    fn as_f64(self) -> f64;
+}
+
+impl FftFloat for f32 {
+    const RAW_ID: u8 = FFT_COMPRESSOR_ID;
+    const QUANTIZED_ID: u8 = FFT_QUANTIZED_ID;
+    fn of_f64(x: f64) -> Self {
+        x as f32
+    }
+    fn as_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl FftFloat for f64 {
+    const RAW_ID: u8 = FFT_F64_ID;
+    const QUANTIZED_ID: u8 = FFT_F64_QUANTIZED_ID;
+    fn of_f64(x: f64) -> Self {
+        x
+    }
+    fn as_f64(self) -> f64 {
+        self
+    }
+}
+
+/// Precision of the FFT pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precision {
+    /// f64 when the values go beyond the integers a f32 holds exactly, f32 otherwise
+    #[default]
+    Auto,
+    F32,
+    F64,
+}
+
+impl Precision {
+    fn resolve(self, min: f64, max: f64) -> Self {
+        match self {
+            Precision::Auto if min.abs().max(max.abs()) > F32_EXACT_INT => Precision::F64,
+            Precision::Auto => Precision::F32,
+            precision => precision,
+        }
+    }
+}
+
 /// Struct to store frequencies, since bincode can't encode num_complex Complex format, this one is compatible
-// This could be a Generic to support f64, integers, etc...
 #[derive(Encode, Decode, Debug, Copy, Clone)]
-pub struct FrequencyPoint {
-    /// Frequency position
-    pos: u16, // This is the reason that frame size is limited to 65535, probably enough
-    freq_real: f32,
-    freq_img: f32,
+pub struct FrequencyPoint<T = f32> {
+    /// Frequency position. Written as a varint, it takes as many bytes as the frame size needs
+    pos: u64,
+    freq_real: T,
+    freq_img: T,
 }
 
-impl FrequencyPoint {
-    pub fn from_complex_with_position(complex: Complex<f32>, pos: u16) -> Self {
+impl<T: Float> FrequencyPoint<T> {
+    pub fn from_complex_with_position(complex: Complex<T>, pos: u64) -> Self {
         FrequencyPoint {
             pos,
             freq_real: complex.re,
@@ -47,23 +115,27 @@ impl FrequencyPoint {
         }
     }
 
-    pub fn to_complex(self) -> Complex<f32> {
+    pub fn pos(&self) -> usize {
+        self.pos as usize
+    }
+
+    pub fn to_complex(self) -> Complex<T> {
         Complex {
             re: self.freq_real,
             im: self.freq_img,
         }
     }
 
-    pub fn to_inv_complex(self) -> Complex<f32> {
+    pub fn to_inv_complex(self) -> Complex<T> {
         Complex {
             re: self.freq_real,
-            im: self.freq_img * -1.0,
+            im: -self.freq_img,
         }
     }
 }
 
 // This is VERY specific for this use case, DO NOT RE-USE! This NORM comparison is false for complex numbers
-impl PartialEq for FrequencyPoint {
+impl<T: Float> PartialEq for FrequencyPoint<T> {
     fn eq(&self, other: &Self) -> bool {
         let c1 = Complex {
             re: self.freq_real,
@@ -77,15 +149,15 @@ impl PartialEq for FrequencyPoint {
     }
 }
 
-impl Eq for FrequencyPoint {}
+impl<T: Float> Eq for FrequencyPoint<T> {}
 
-impl PartialOrd for FrequencyPoint {
+impl<T: Float> PartialOrd for FrequencyPoint<T> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Ord for FrequencyPoint {
+impl<T: Float> Ord for FrequencyPoint<T> {
     fn cmp(&self, other: &Self) -> Ordering {
         let c1 = Complex {
             re: self.freq_real,
@@ -105,76 +177,227 @@ impl Ord for FrequencyPoint {
     }
 }
 
+/// Error driven search for the number of coefficients to keep, shared by the frequency domain compressors.
+/// Starts from `max_freq` coefficients and keeps adding more until `attempt`, that keeps the given number of
+/// coefficients and returns the resulting error, is within `max_err`. Returns the error of the last attempt.
+/// NOTE: This does not otimize for smallest possible error, just being smaller than the error.
+pub(crate) fn bounded_search(
+    max_freq: usize,
+    max_err: f64,
+    mut attempt: impl FnMut(usize) -> f64,
+) -> f64 {
+    // To make sure we run the first cycle
+    let mut current_err = max_err + 1.0;
+    let mut jump: usize = 0;
+    let mut iterations = 0;
+    // Aproximation. Faster convergence
+    while ((max_err * 1000.0) as i32) < ((current_err * 1000.0) as i32) {
+        iterations += 1;
+        current_err = attempt(max_freq + jump);
+        trace!("Current Err: {}", current_err);
+        // Max iterations is 22 (We start at 10%, we can go to 95% and 1% at a time)
+        match iterations {
+            1..=17 => jump += (max_freq / 2).max(1),
+            18..=22 => jump += (max_freq / 10).max(1),
+            _ => break,
+        }
+    }
+    debug!(
+        "Iterations to convergence: {}, Freqs P:{}, Error: {}",
+        iterations,
+        jump + max_freq,
+        current_err
+    );
+    current_err
+}
+
+/// Quantized frequencies, as stored. Frequencies are sorted by position, each one is written as the distance to the
+/// previous position followed by its real and imaginary parts as zigzag encoded multiples of `2^exponent`. Every
+/// field takes the bits of the biggest value of its kind.
+#[derive(Encode, Decode, Debug)]
+struct PackedFrequencies {
+    exponent: i8,
+    count: u32,
+    position_bits: u8,
+    value_bits: u8,
+    data: Vec<u8>,
+}
+
+impl PackedFrequencies {
+    fn pack<T: FftFloat>(frequencies: &[FrequencyPoint<T>], exponent: i8) -> Self {
+        let step = T::of_f64(2f64.powi(exponent as i32));
+        let mut sorted = frequencies.to_vec();
+        sorted.sort_by_key(|f| f.pos);
+        let mut previous = 0;
+        let fields: Vec<(u64, u64, u64)> = sorted
+            .iter()
+            .map(|f| {
+                let gap = f.pos - previous;
+                previous = f.pos;
+                let re = zigzag((f.freq_real / step).round().as_f64() as i64);
+                let im = zigzag((f.freq_img / step).round().as_f64() as i64);
+                (gap, re, im)
+            })
+            .collect();
+        let position_bits = bit_width(fields.iter().map(|f| f.0).max().unwrap_or(0)) as u8;
+        let value_bits = bit_width(fields.iter().map(|f| f.1.max(f.2)).max().unwrap_or(0)) as u8;
+        let mut writer = BitWriter::new();
+        for (gap, re, im) in fields {
+            writer.write_bits(gap, position_bits as u32);
+            writer.write_bits(re, value_bits as u32);
+            writer.write_bits(im, value_bits as u32);
+        }
+        PackedFrequencies {
+            exponent,
+            count: sorted.len() as u32,
+            position_bits,
+            value_bits,
+            data: writer.finish(),
+        }
+    }
+
+    fn unpack<T: FftFloat>(&self) -> Result<Vec<FrequencyPoint<T>>, Error> {
+        if self.position_bits > 64 || self.value_bits > 64 {
+            return Err(Error::CorruptData(
+                "FFT quantized frequencies header".to_string(),
+            ));
+        }
+        let step = T::of_f64(2f64.powi(self.exponent as i32));
+        let mut reader = BitReader::new(&self.data);
+        let mut pos: u64 = 0;
+        // Every frequency after the first takes at least a bit, a corrupted count can't allocate more than that
+        let mut frequencies =
+            Vec::with_capacity((self.count as usize).min(self.data.len() * 8 + 1));
+        for i in 0..self.count {
+            let gap = reader.read_bits(self.position_bits as u32)?;
+            // At most one frequency per position
+            if i > 0 && gap == 0 {
+                return Err(Error::CorruptData(
+                    "FFT frequency position repeated".to_string(),
+                ));
+            }
+            pos = pos
+                .checked_add(gap)
+                .ok_or_else(|| Error::CorruptData("FFT frequency position overflow".to_string()))?;
+            let re = T::of_f64(unzigzag(reader.read_bits(self.value_bits as u32)?) as f64) * step;
+            let im = T::of_f64(unzigzag(reader.read_bits(self.value_bits as u32)?) as f64) * step;
+            frequencies.push(FrequencyPoint::from_complex_with_position(
+                Complex { re, im },
+                pos,
+            ));
+        }
+        Ok(frequencies)
+    }
+}
+
+/// Biggest power of 2 step for the frequencies that keeps their rounding within `budget` of every sample, as its
+/// exponent. Rounding moves each of the `count` frequencies by at most half a step in each part, which moves a
+/// sample by at most `2 * step / len` once mirrored and normalized.
+fn quantum_exponent(budget: f64, len: usize, count: usize) -> Option<i8> {
+    let step = budget * len as f64 / (2.0 * count.max(1) as f64);
+    let exponent = step.log2().floor();
+    // Far from the f32 limits, so the multiples of the step stay normal numbers
+    (exponent.is_finite() && (-100.0..=100.0).contains(&exponent)).then_some(exponent as i8)
+}
+
 /// FFT Compressor. Applies FFT to a signal, picks the N best frequencies, discards the rest. Always LOSSY
+///
+/// With an error budget (`compress_bounded`) the frequencies are also quantized to multiples of a power of 2, and
+/// packed with just the bits they need instead of a varint position and two floats each.
+/// The pipeline runs on f32 by default, f64 keeps the precision of series with values in the billions.
 #[derive(PartialEq, Debug)]
-pub struct FFT {
+pub struct FFT<T: FftFloat = f32> {
     pub id: u8,
-    pub frequencies: Vec<FrequencyPoint>,
-    pub max_value: f32,
-    pub min_value: f32,
+    pub frequencies: Vec<FrequencyPoint<T>>,
+    pub max_value: T,
+    pub min_value: T,
+    /// Frequencies are multiples of `2^quantum`, when quantized
+    pub quantum: Option<i8>,
     pub error: Option<f64>,
 }
 
 // Implementing the Encode manually because we don't want to encode the Error field, less bytes used.
-impl Encode for FFT {
+impl<T: FftFloat> Encode for FFT<T> {
     fn encode<__E: ::bincode::enc::Encoder>(
         &self,
         encoder: &mut __E,
     ) -> Result<(), ::bincode::error::EncodeError> {
-        Encode::encode(&self.id, encoder)?;
-        Encode::encode(&self.frequencies, encoder)?;
+        match self.quantum {
+            Some(exponent) => {
+                Encode::encode(&T::QUANTIZED_ID, encoder)?;
+                Encode::encode(
+                    &PackedFrequencies::pack(&self.frequencies, exponent),
+                    encoder,
+                )?;
+            }
+            None => {
+                Encode::encode(&T::RAW_ID, encoder)?;
+                Encode::encode(&self.frequencies, encoder)?;
+            }
+        }
         Encode::encode(&self.max_value, encoder)?;
         Encode::encode(&self.min_value, encoder)?;
         Ok(())
     }
 }
 
-impl Decode for FFT {
+impl<T: FftFloat> Decode for FFT<T> {
     fn decode<__D: ::bincode::de::Decoder>(
         decoder: &mut __D,
     ) -> Result<Self, ::bincode::error::DecodeError> {
+        let id: u8 = Decode::decode(decoder)?;
+        let (frequencies, quantum) = if id == T::QUANTIZED_ID {
+            let packed: PackedFrequencies = Decode::decode(decoder)?;
+            let frequencies = packed
+                .unpack()
+                .map_err(|e| DecodeError::OtherString(e.to_string()))?;
+            (frequencies, Some(packed.exponent))
+        } else if id == T::RAW_ID {
+            (Decode::decode(decoder)?, None)
+        } else {
+            return Err(DecodeError::OtherString(format!(
+                "FFT payload id {} with another precision",
+                id
+            )));
+        };
         Ok(Self {
-            id: Decode::decode(decoder)?,
-            frequencies: Decode::decode(decoder)?,
+            id: FFT_COMPRESSOR_ID,
+            frequencies,
             max_value: Decode::decode(decoder)?,
             min_value: Decode::decode(decoder)?,
+            quantum,
             error: None,
         })
     }
 }
 
-impl<'__de> ::bincode::BorrowDecode<'__de> for FFT {
+impl<'__de, T: FftFloat> ::bincode::BorrowDecode<'__de> for FFT<T> {
     fn borrow_decode<__D: ::bincode::de::BorrowDecoder<'__de>>(
         decoder: &mut __D,
     ) -> Result<Self, ::bincode::error::DecodeError> {
-        Ok(Self {
-            id: ::bincode::BorrowDecode::borrow_decode(decoder)?,
-            frequencies: ::bincode::BorrowDecode::borrow_decode(decoder)?,
-            max_value: ::bincode::BorrowDecode::borrow_decode(decoder)?,
-            min_value: ::bincode::BorrowDecode::borrow_decode(decoder)?,
-            error: None,
-        })
+        Decode::decode(decoder)
     }
 }
 
-impl FFT {
+impl<T: FftFloat> FFT<T> {
     /// Creates a new instance of the Constant compressor with the size needed to handle the worst case
     pub fn new(sample_count: usize, min: f64, max: f64) -> Self {
         debug!("FFT compressor: min:{} max:{}", min, max);
         FFT {
             id: FFT_COMPRESSOR_ID,
             frequencies: Vec::with_capacity(sample_count),
-            max_value: FFT::f64_to_f32(max),
-            min_value: FFT::f64_to_f32(min),
+            max_value: FFT::to_float(max),
+            min_value: FFT::to_float(min),
+            quantum: None,
             error: None,
         }
     }
 
-    fn f64_to_f32(x: f64) -> f32 {
-        let y = x as f32;
+    fn to_float(x: f64) -> T {
+        let y = T::of_f64(x);
         if !(x.is_finite() && y.is_finite()) {
             // PANIC? Error?
-            error!("f32 overflow during conversion");
+            error!("Overflow during conversion, the f64 precision holds it");
         }
         y
     }
@@ -205,30 +428,30 @@ impl FFT {
 
     /// Rounds a number to the specified number of decimal places
     // TODO: Move this into utils? I think this will be helpfull somewhere else.
-    fn round(&self, x: f32, decimals: u32) -> f64 {
+    fn round(&self, x: T, decimals: u32) -> f64 {
         let y = 10i32.pow(decimals) as f64;
-        let out = (x as f64 * y).round() / y;
-        if out > self.max_value as f64 {
-            return self.max_value as f64;
+        let out = (x.as_f64() * y).round() / y;
+        if out > self.max_value.as_f64() {
+            return self.max_value.as_f64();
         }
-        if out < self.min_value as f64 {
-            return self.min_value as f64;
+        if out < self.min_value.as_f64() {
+            return self.min_value.as_f64();
         }
         out
     }
 
-    // Converts an f64 vec to an Vec of Complex F32
-    fn optimize(data: &[f64]) -> Vec<Complex<f32>> {
+    // Converts an f64 vec to an Vec of Complex of the pipeline precision
+    fn optimize(data: &[f64]) -> Vec<Complex<T>> {
         data.iter()
             .map(|x| Complex {
-                re: FFT::f64_to_f32(*x),
-                im: 0.0f32,
+                re: FFT::to_float(*x),
+                im: T::zero(),
             })
             .collect()
     }
 
     /// Removes the smallest frequencies from `buffer` until `max_freq` remain
-    fn fft_trim(buffer: &mut [Complex<f32>], max_freq: usize) -> Vec<FrequencyPoint> {
+    pub(crate) fn fft_trim(buffer: &mut [Complex<T>], max_freq: usize) -> Vec<FrequencyPoint<T>> {
         let mut freq_vec = Vec::with_capacity(max_freq);
         if max_freq == 1 {
             freq_vec.push(FrequencyPoint::from_complex_with_position(buffer[0], 0));
@@ -236,10 +459,10 @@ impl FFT {
         }
         // More than 1 frequency needed, get the biggest frequencies now.
         // Move from the buffer into Frequency Vectors
-        let tmp_vec: Vec<FrequencyPoint> = buffer
+        let tmp_vec: Vec<FrequencyPoint<T>> = buffer
             .iter()
             .enumerate()
-            .map(|(pos, &f)| FrequencyPoint::from_complex_with_position(f, pos as u16))
+            .map(|(pos, &f)| FrequencyPoint::from_complex_with_position(f, pos as u64))
             .collect();
         // This part, is because Binary heap is very good at "give me the top N elements"
         let mut heap = BinaryHeap::from(tmp_vec);
@@ -247,7 +470,7 @@ impl FFT {
         for _ in 0..max_freq {
             if let Some(item) = heap.pop() {
                 // If the frequency is 0, we don't need it or any other
-                if item.freq_img == 0.0 && item.freq_real == 0.0 {
+                if item.freq_img.is_zero() && item.freq_real.is_zero() {
                     break;
                 }
                 freq_vec.push(item)
@@ -256,6 +479,29 @@ impl FFT {
         freq_vec
     }
 
+    /// Rounds the frequencies to multiples of `2^exponent`. Frequencies too big to be written as multiples of it are
+    /// left as they are.
+    fn quantize(&mut self, exponent: Option<i8>) {
+        self.quantum = None;
+        let Some(exponent) = exponent else {
+            return;
+        };
+        let step = T::of_f64(2f64.powi(exponent as i32));
+        let limit = T::of_f64(2f64.powi(62));
+        if self
+            .frequencies
+            .iter()
+            .any(|f| (f.freq_real / step).abs() >= limit || (f.freq_img / step).abs() >= limit)
+        {
+            return;
+        }
+        for f in self.frequencies.iter_mut() {
+            f.freq_real = (f.freq_real / step).round() * step;
+            f.freq_img = (f.freq_img / step).round() * step;
+        }
+        self.quantum = Some(exponent);
+    }
+
     /// Compress data via FFT.
     /// This picks a set of data, computes the FFT, and uses the hinted number of frequencies to store the N provided
     /// more relevant frequencies
@@ -283,7 +529,8 @@ impl FFT {
 
     /// Compress data via FFT - EXPENSIVE
     /// This picks a set of data, computes the FFT, and optimizes the number of frequencies to store to match
-    /// the max allowed error.
+    /// the max allowed error. The frequencies are quantized within a share of the error, the error of each attempt
+    /// is measured after the quantization.
     /// NOTE: This does not otimize for smallest possible error, just being smaller than the error.
     pub fn compress_bounded(&mut self, data: &[f64], max_err: f64) {
         if self.max_value == self.min_value {
@@ -303,13 +550,16 @@ impl FFT {
 
         // Should we apply a Gibbs sizing?
         let g_data: &[f64] = if data.len() >= 128 {
-            &FFT::gibbs_sizing(data)
+            &Self::gibbs_sizing(data)
         } else {
             data
         };
 
         let len = g_data.len();
-        let len_f32 = len as f32;
+        let len_t = T::of_f64(len as f64);
+        // Allowed distance of each sample, from the average magnitude of the data
+        let mean_abs = data.iter().map(|x| x.abs()).sum::<f64>() / data.len() as f64;
+        let quantization_budget = max_err * mean_abs * QUANTIZATION_SHARE;
 
         // Clean the data
         let mut buffer = FFT::optimize(g_data);
@@ -326,37 +576,28 @@ impl FFT {
         // and the first one being the dc component
         let size = (buff_clone.len() / 2) + 1;
         buff_clone.truncate(size);
-        // To make sure we run the first cycle
-        let mut current_err = max_err + 1.0;
-        let mut jump: usize = 0;
-        let mut iterations = 0;
-        // Aproximation. Faster convergence
-        while ((max_err * 1000.0) as i32) < ((current_err * 1000.0) as i32) {
-            iterations += 1;
-            self.frequencies = FFT::fft_trim(&mut buff_clone, max_freq + jump);
+        let current_err = bounded_search(max_freq, max_err, |freqs| {
+            self.frequencies = FFT::fft_trim(&mut buff_clone, freqs);
+            self.quantize(quantum_exponent(
+                quantization_budget,
+                len,
+                self.frequencies.len(),
+            ));
             // Inverse FFT and error check
             let mut idata = self.get_mirrored_freqs(len);
             // run the ifft
             ifft.process(&mut idata);
             let out_data: Vec<f64> = idata
                 .iter()
-                .map(|&f| self.round(f.re / len_f32, DECIMAL_PRECISION.into()))
+                .map(|&f| self.round(f.re / len_t, DECIMAL_PRECISION.into()))
                 .collect();
-            current_err = calculate_error(g_data, &out_data);
-            trace!("Current Err: {}", current_err);
-            // Max iterations is 22 (We start at 10%, we can go to 95% and 1% at a time)
-            match iterations {
-                1..=17 => jump += (max_freq / 2).max(1),
-                18..=22 => jump += (max_freq / 10).max(1),
-                _ => break,
-            }
-        }
+            calculate_error(g_data, &out_data)
+        });
         self.error = Some(current_err);
         debug!(
-            "Iterations to convergence: {}, Freqs P:{} S:{}, Error: {}",
-            iterations,
-            jump + max_freq,
+            "Freqs S:{}, Quantum: {:?}, Error: {}",
             self.frequencies.len(),
+            self.quantum,
             current_err
         );
     }
@@ -386,10 +627,10 @@ impl FFT {
         buffer.truncate(size);
         self.frequencies = FFT::fft_trim(&mut buffer, max_freq);
     }
-    pub fn decompress(data: &[u8]) -> Self {
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
         let config = BinConfig::get();
-        let (fft, _) = bincode::decode_from_slice(data, config).unwrap();
-        fft
+        let (fft, _) = bincode::decode_from_slice(data, config)?;
+        Ok(fft)
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
@@ -398,16 +639,10 @@ impl FFT {
     }
 
     /// Gets the full sized array with the frequencies mirrored
-    fn get_mirrored_freqs(&self, len: usize) -> Vec<Complex<f32>> {
+    fn get_mirrored_freqs(&self, len: usize) -> Vec<Complex<T>> {
         // Because we are dealing with Real inputs, we only store half the frequencies, but
         // we need all for the ifft
-        let mut data = vec![
-            Complex {
-                re: 0.0f32,
-                im: 0.0f32
-            };
-            len
-        ];
+        let mut data = vec![Complex::new(T::zero(), T::zero()); len];
         for f in &self.frequencies {
             let pos = f.pos as usize;
             data[pos] = f.to_complex();
@@ -423,10 +658,10 @@ impl FFT {
 
     /// Returns an array of data
     /// Runs the ifft, and push residuals into place and/or adjusts max and mins accordingly
-    pub fn to_data(&self, frame_size: usize) -> Vec<f64> {
+    pub fn to_data(&self, frame_size: usize) -> Result<Vec<f64>, Error> {
         if self.max_value == self.min_value {
             debug!("Same max and min, faster decompression!");
-            return vec![self.max_value as f64; frame_size];
+            return Ok(vec![self.max_value.as_f64(); frame_size]);
         }
         // Was this processed to reduce the Gibbs phenomeon?
         let trim_sizes = if frame_size >= 128 {
@@ -442,6 +677,15 @@ impl FFT {
             (0, 0)
         };
         let gibbs_frame_size = frame_size + trim_sizes.0 + trim_sizes.1;
+        if self
+            .frequencies
+            .iter()
+            .any(|f| f.pos > (gibbs_frame_size / 2) as u64)
+        {
+            return Err(Error::CorruptData(
+                "FFT frequency out of the frame range".to_string(),
+            ));
+        }
         // Vec to process the ifft
         let mut data = self.get_mirrored_freqs(gibbs_frame_size);
         // Plan the ifft
@@ -450,97 +694,225 @@ impl FFT {
         // run the ifft
         fft.process(&mut data);
         // We need this for normalization
-        let len = gibbs_frame_size as f32;
+        let len = T::of_f64(gibbs_frame_size as f64);
         // We only need the real part
-        data.iter()
+        Ok(data
+            .iter()
             // trim the exceses data
             .skip(trim_sizes.0)
             .take(data.len() - trim_sizes.0 - trim_sizes.1)
             // We only need the real part
             .map(|&f| self.round(f.re / len, DECIMAL_PRECISION.into()))
-            .collect()
+            .collect())
     }
 }
 
-/// Compresses a data segment via FFT.
-pub fn fft(data: &[f64]) -> Vec<u8> {
-    info!("Initializing FFT Compressor");
-    let mut min = data[0];
-    let mut max = data[0];
-    for e in data.iter() {
-        if e > &max {
-            max = *e
-        };
-        if e < &min {
-            min = *e
-        };
-    }
+/// How the frequencies to keep are picked
+enum Selection {
+    /// 1/100 of the data length OR 3, which is bigger
+    Default,
+    /// This many
+    Count(usize),
+    /// As many as needed to be within this error
+    Error(f64),
+}
+
+/// Runs the compressor on the precision `T`
+fn compress_with<T: FftFloat>(
+    data: &[f64],
+    min: f64,
+    max: f64,
+    selection: Selection,
+) -> CompressorResult {
     // Initialize the compressor
-    let mut c = FFT::new(data.len(), min, max);
+    let mut c = FFT::<T>::new(data.len(), min, max);
     // Convert the data
-    c.compress(data);
+    match selection {
+        Selection::Default => c.compress(data),
+        Selection::Count(freqs) => c.compress_hinted(data, freqs),
+        Selection::Error(max_err) => c.compress_bounded(data, max_err),
+    }
     // Convert to bytes
-    c.to_bytes()
+    CompressorResult::new(c.to_bytes(), c.error.unwrap_or(0.0))
+}
+
+fn compress_with_precision(
+    data: &[f64],
+    min: f64,
+    max: f64,
+    precision: Precision,
+    selection: Selection,
+) -> CompressorResult {
+    match precision.resolve(min, max) {
+        Precision::F64 => {
+            debug!("FFT on f64, min:{} max:{}", min, max);
+            compress_with::<f64>(data, min, max, selection)
+        }
+        _ => compress_with::<f32>(data, min, max, selection),
+    }
 }
 
-/// Uncompress a FFT data
-pub fn fft_to_data(sample_number: usize, compressed_data: &[u8]) -> Vec<f64> {
-    let c = FFT::decompress(compressed_data);
-    c.to_data(sample_number)
+/// Compresses a data segment via FFT.
+pub fn fft(data: &[f64]) -> Vec<u8> {
+    info!("Initializing FFT Compressor");
+    let stats = DataStats::new(data);
+    compress_with_precision(
+        data,
+        stats.min,
+        stats.max,
+        Precision::Auto,
+        Selection::Default,
+    )
+    .compressed_data
+}
+
+/// Uncompress a FFT data, on the precision it was compressed with
+pub fn fft_to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    match compressed_data.first() {
+        Some(&(FFT_F64_ID | FFT_F64_QUANTIZED_ID)) => {
+            FFT::<f64>::decompress(compressed_data)?.to_data(sample_number)
+        }
+        _ => FFT::<f32>::decompress(compressed_data)?.to_data(sample_number),
+    }
 }
 
 /// Compress targeting a specific max error allowed. This is very computational intensive,
 /// as the FFT will be calculated over and over until the specific error threshold is achived.
 pub fn fft_allowed_error(data: &[f64], allowed_error: f64) -> CompressorResult {
-    info!("Initializing FFT Compressor. Max error: {}", allowed_error);
-    let mut min = data[0];
-    let mut max = data[0];
-    for e in data.iter() {
-        if e > &max {
-            max = *e
-        };
-        if e < &min {
-            min = *e
-        };
-    }
-    // Initialize the compressor
-    let mut c = FFT::new(data.len(), min, max);
-    // Convert the data
-    c.compress_bounded(data, allowed_error);
-    // Convert to bytes
-    CompressorResult::new(c.to_bytes(), c.error.unwrap_or(0.0))
+    fft_allowed_error_with_precision(data, allowed_error, Precision::Auto)
+}
+
+/// Same as `fft_allowed_error`, on the given precision instead of the one picked from the data
+pub fn fft_allowed_error_with_precision(
+    data: &[f64],
+    allowed_error: f64,
+    precision: Precision,
+) -> CompressorResult {
+    info!(
+        "Initializing FFT Compressor. Max error: {} Precision: {:?}",
+        allowed_error, precision
+    );
+    let stats = DataStats::new(data);
+    compress_with_precision(
+        data,
+        stats.min,
+        stats.max,
+        precision,
+        Selection::Error(allowed_error),
+    )
 }
 
 /// Compress targeting a specific max error allowed. This is very computational intensive,
 /// as the FFT will be calculated over and over until the specific error threshold is achived.
 pub fn fft_compressor(data: &[f64], allowed_error: f64, stats: DataStats) -> CompressorResult {
     debug!("Initializing FFT Compressor. Error and Stats provided");
-    // Initialize the compressor
-    let mut c = FFT::new(data.len(), stats.min, stats.max);
-    // Convert the data
-    c.compress_bounded(data, allowed_error);
-    // Convert to bytes
-    CompressorResult::new(c.to_bytes(), c.error.unwrap_or(0.0))
+    compress_with_precision(
+        data,
+        stats.min,
+        stats.max,
+        Precision::Auto,
+        Selection::Error(allowed_error),
+    )
 }
 
 pub fn fft_set(data: &[f64], freqs: usize) -> Vec<u8> {
     info!("Initializing FFT Compressor");
-    let mut min = data[0];
-    let mut max = data[0];
-    for e in data.iter() {
-        if e > &max {
-            max = *e
-        };
-        if e < &min {
-            min = *e
-        };
+    let stats = DataStats::new(data);
+    compress_with_precision(
+        data,
+        stats.min,
+        stats.max,
+        Precision::Auto,
+        Selection::Count(freqs),
+    )
+    .compressed_data
+}
+
+/// Number of lags tried between the periods of the bins next to the peak, before settling on the best one
+const PERIOD_STEPS: usize = 64;
+
+/// Removes the least squares line through the data
+fn remove_line(data: &[f64]) -> Vec<f64> {
+    let len = data.len() as f64;
+    let mean_x = (len - 1.0) / 2.0;
+    let mean_y = data.iter().sum::<f64>() / len;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (i, y) in data.iter().enumerate() {
+        covariance += (i as f64 - mean_x) * (y - mean_y);
+        variance += (i as f64 - mean_x).powi(2);
     }
-    // Initialize the compressor
-    let mut c = FFT::new(data.len(), min, max);
-    // Convert the data
-    c.compress_hinted(data, freqs);
-    // Convert to bytes
-    c.to_bytes()
+    let slope = if variance > 0.0 {
+        covariance / variance
+    } else {
+        0.0
+    };
+    data.iter()
+        .enumerate()
+        .map(|(i, y)| y - mean_y - slope * (i as f64 - mean_x))
+        .collect()
+}
+
+/// Period, in samples, of the strongest cycle in the data that repeats at least twice. The peak of the spectrum only
+/// gives it to the nearest bin, so it is then settled on the lag with the highest autocorrelation between the periods
+/// of the bins next to the peak.
+/// None if the data has no cycle at all (too short, constant, a straight line).
+pub fn dominant_period(data: &[f64]) -> Option<usize> {
+    let len = data.len();
+    if len < 4 {
+        return None;
+    }
+    // A trend would take over the lowest frequencies
+    let detrended = remove_line(data);
+    let mut buffer: Vec<Complex<f64>> = detrended
+        .iter()
+        .map(|&re| Complex { re, im: 0.0 })
+        .collect();
+    FftPlanner::new().plan_fft_forward(len).process(&mut buffer);
+    let peak = (2..=len / 2).max_by(|&a, &b| buffer[a].norm().total_cmp(&buffer[b].norm()))?;
+    if buffer[peak].norm() == 0.0 {
+        return None;
+    }
+    // Correlation of the data with itself `lag` samples later
+    let autocorrelation = |lag: usize| {
+        let (head, tail) = (&detrended[..len - lag], &detrended[lag..]);
+        let count = head.len() as f64;
+        let (mean_a, mean_b) = (
+            head.iter().sum::<f64>() / count,
+            tail.iter().sum::<f64>() / count,
+        );
+        let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+        for (a, b) in head.iter().zip(tail.iter()) {
+            covariance += (a - mean_a) * (b - mean_b);
+            variance_a += (a - mean_a).powi(2);
+            variance_b += (b - mean_b).powi(2);
+        }
+        let scale = (variance_a * variance_b).sqrt();
+        if scale > 0.0 {
+            covariance / scale
+        } else {
+            0.0
+        }
+    };
+    let best = |lags: &mut dyn Iterator<Item = usize>| {
+        lags.map(|lag| (lag, autocorrelation(lag)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(lag, _)| lag)
+    };
+    let low = (len / (peak + 1)).max(2);
+    let high = (len / (peak - 1)).min(len / 2);
+    if low > high {
+        return Some(len / peak);
+    }
+    // Coarse steps over the range first, then every lag around the best one
+    let step = ((high - low) / PERIOD_STEPS).max(1);
+    let coarse = best(&mut (low..=high).step_by(step))?;
+    let period = best(&mut (coarse.saturating_sub(step).max(low)..=(coarse + step).min(high)))?;
+    trace!(
+        "Spectrum peak at bin {}, period of {} samples",
+        peak,
+        period
+    );
+    Some(period)
 }
 
 #[cfg(test)]
@@ -563,7 +935,7 @@ mod tests {
     fn test_to_lossless_data() {
         let vector1 = vec![1.0, 1.0, 1.0, 1.0, 2.0, 1.0, 1.0, 1.0, 3.0, 1.0, 1.0, 5.0];
         let compressed_data = fft_set(&vector1, 12);
-        let out = fft_to_data(vector1.len(), &compressed_data);
+        let out = fft_to_data(vector1.len(), &compressed_data).unwrap();
         assert_eq!(vector1, out);
     }
 
@@ -574,7 +946,7 @@ mod tests {
             1.0, 1.87201, 2.25, 1.0, 1.82735, 1.689, 1.82735, 1.0, 2.75, 1.189, 1.0, 3.311,
         ];
         let compressed_data = fft(&vector1);
-        let out = fft_to_data(vector1.len(), &compressed_data);
+        let out = fft_to_data(vector1.len(), &compressed_data).unwrap();
         assert_eq!(lossy_vec, out);
     }
 
@@ -583,17 +955,114 @@ mod tests {
         let vector1 = vec![1.0, 1.0, 1.0, 1.0, 2.0, 1.0, 1.0, 1.0, 3.0, 1.0, 1.0, 5.0];
         let frame_size = vector1.len();
         let compressed_result = fft_allowed_error(&vector1, 0.01);
-        let out = FFT::decompress(&compressed_result.compressed_data).to_data(frame_size);
+        let out = FFT::<f32>::decompress(&compressed_result.compressed_data)
+            .unwrap()
+            .to_data(frame_size)
+            .unwrap();
         let e = calculate_error(&vector1, &out);
         assert!(e <= 0.01);
     }
 
+    #[test]
+    fn test_quantized_frequencies() {
+        // Heap usage, a slow trend with a daily cycle and noise
+        let vector1: Vec<f64> = (0..4096)
+            .map(|i| {
+                let t = i as f64;
+                2e9 + t * 1e5 + (t / 300.0).sin() * 1e8 + ((i * 7919) % 101) as f64 * 1e5
+            })
+            .collect();
+        let stats = DataStats::new(&vector1);
+        let mut c = FFT::<f32>::new(vector1.len(), stats.min, stats.max);
+        c.compress_bounded(&vector1, 0.01);
+        assert!(c.quantum.is_some());
+        let compressed_data = c.to_bytes();
+        assert_eq!(compressed_data[0], FFT_QUANTIZED_ID);
+        // A fraction of the 10 bytes a frequency takes as f32
+        assert!(compressed_data.len() < c.frequencies.len() * 5);
+        let decompressed = FFT::<f32>::decompress(&compressed_data).unwrap();
+        assert_eq!(decompressed.quantum, c.quantum);
+        let out = decompressed.to_data(vector1.len()).unwrap();
+        assert!(calculate_error(&vector1, &out) <= 0.01);
+        // Frequencies past the end of the packed data
+        let mut packed = PackedFrequencies::pack(&c.frequencies, c.quantum.unwrap());
+        packed.count += 1;
+        assert!(matches!(packed.unpack::<f32>(), Err(Error::TruncatedInput)));
+        packed.value_bits = 65;
+        assert!(packed.unpack::<f32>().is_err());
+    }
+
+    #[test]
+    fn test_f64_precision() {
+        // Heap goal in bytes, steps of a few bytes are lost in a f32 at this size. Short frame, no Gibbs sizing
+        let vector1: Vec<f64> = (0..100)
+            .map(|i| 4_000_000_000.0 + ((i * 37) % 11) as f64 * 3.0)
+            .collect();
+        let compressed_data = fft_set(&vector1, vector1.len());
+        assert_eq!(compressed_data[0], FFT_F64_ID);
+        assert_eq!(
+            fft_to_data(vector1.len(), &compressed_data).unwrap(),
+            vector1
+        );
+        let result = fft_allowed_error(&vector1, 0.0001);
+        assert_eq!(result.compressed_data[0], FFT_F64_QUANTIZED_ID);
+        let out = fft_to_data(vector1.len(), &result.compressed_data).unwrap();
+        assert!(calculate_error(&vector1, &out) <= 0.0001);
+        // Asked for explicitly
+        let vector2 = vec![1.0, 1.0, 1.0, 1.0, 2.0, 1.0, 1.0, 1.0, 3.0, 1.0, 1.0, 5.0];
+        let result = fft_allowed_error_with_precision(&vector2, 0.01, Precision::F64);
+        assert_eq!(result.compressed_data[0], FFT_F64_QUANTIZED_ID);
+        let out = fft_to_data(vector2.len(), &result.compressed_data).unwrap();
+        assert!(calculate_error(&vector2, &out) <= 0.01);
+        // The precision of the payload has to match
+        assert!(FFT::<f32>::decompress(&result.compressed_data).is_err());
+    }
+
+    #[test]
+    fn test_max_frame_size() {
+        // The biggest frame of the optimizer, with a component right at the top of the spectrum
+        let vector1: Vec<f64> = (0..131072)
+            .map(|i| {
+                let alternating = if i % 2 == 0 { 50.0 } else { -50.0 };
+                1000.0 + (i as f64 / 5000.0).sin() * 200.0 + alternating
+            })
+            .collect();
+        let stats = DataStats::new(&vector1);
+        let mut c = FFT::<f32>::new(vector1.len(), stats.min, stats.max);
+        c.compress_bounded(&vector1, 0.01);
+        assert!(c.frequencies.iter().any(|f| f.pos() > u16::MAX as usize));
+        let out = fft_to_data(vector1.len(), &c.to_bytes()).unwrap();
+        assert!(calculate_error(&vector1, &out) <= 0.01);
+        // Same positions with the frequencies as f32
+        c.quantum = None;
+        let decompressed = FFT::<f32>::decompress(&c.to_bytes()).unwrap();
+        let positions = |c: &FFT| c.frequencies.iter().map(|f| f.pos()).collect::<Vec<_>>();
+        assert_eq!(positions(&decompressed), positions(&c));
+    }
+
+    #[test]
+    fn test_dominant_period() {
+        // A daily cycle of 1440 samples over a trend, the frame doesn't hold a whole number of cycles
+        let vector1: Vec<f64> = (0..5000)
+            .map(|i| {
+                200.0
+                    + i as f64 * 0.01
+                    + (i as f64 * 2.0 * std::f64::consts::PI / 1440.0).sin() * 30.0
+            })
+            .collect();
+        assert_eq!(dominant_period(&vector1), Some(1440));
+        let vector2: Vec<f64> = (0..64).map(|i| (i % 8) as f64).collect();
+        assert_eq!(dominant_period(&vector2), Some(8));
+        assert_eq!(dominant_period(&[1.0; 100]), None);
+        assert_eq!(dominant_period(&[1.0, 2.0]), None);
+    }
+
     #[test]
     fn test_gibbs_sizing() {
         let mut vector1 = vec![2.0; 2048];
         vector1[0] = 1.0;
         vector1[2047] = 3.0;
-        let vector1_sized = FFT::gibbs_sizing(&vector1);
+        let vector1_sized = FFT::<f32>::gibbs_sizing(&vector1);
         assert_eq!(vector1_sized.len(), 2187);
         assert!(vector1_sized[2] == 1.0);
         assert!(vector1_sized[2185] == 3.0);
@@ -615,12 +1084,15 @@ mod tests {
             };
         }
         // Initialize the compressor
-        let mut c = FFT::new(frame_size, min, max);
+        let mut c = FFT::<f32>::new(frame_size, min, max);
         // Convert the data
         c.compress(&vector1);
         let frequencies_total = c.frequencies.len();
         let compressed_data = c.to_bytes();
-        let out = FFT::decompress(&compressed_data).to_data(frame_size);
+        let out = FFT::<f32>::decompress(&compressed_data)
+            .unwrap()
+            .to_data(frame_size)
+            .unwrap();
         assert_eq!(vector1, out);
         assert_eq!(frequencies_total, 0);
     }
@@ -0,0 +1,198 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use super::BinConfig;
+use crate::error::Error;
+use crate::utils::bits::{BitReader, BitWriter};
+use bincode::{Decode, Encode};
+use log::{debug, info};
+
+const GORILLA_COMPRESSOR_ID: u8 = 45;
+
+/// Lossless compressor for floats, as described in the Gorilla paper (Facebook, VLDB 2015).
+///
+/// Each value is XORed with the previous one, slow changing series share most of their bits so the result is
+/// mostly zeros. The first value is stored as is, then for each XOR:
+/// - `0`: same value as the previous one
+/// - `10`: the meaningful bits fit in the window of the previous XOR, only those bits follow
+/// - `11`: 6 bits with the leading zeros, 6 bits with the length of the meaningful bits minus 1 and the bits
+#[derive(Encode, Decode, PartialEq, Debug, Clone)]
+pub struct Gorilla {
+    pub id: u8,
+    pub data: Vec<u8>,
+}
+
+impl Gorilla {
+    pub fn new() -> Self {
+        debug!("Gorilla compressor");
+        Gorilla {
+            id: GORILLA_COMPRESSOR_ID,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn compress(&mut self, data: &[f64]) {
+        let mut writer = BitWriter::new();
+        let mut previous = 0;
+        // Window of the meaningful bits of the previous XOR, (leading zeros, length)
+        let mut window: Option<(u32, u32)> = None;
+        for (i, value) in data.iter().enumerate() {
+            let bits = value.to_bits();
+            if i == 0 {
+                writer.write_bits(bits, 64);
+                previous = bits;
+                continue;
+            }
+            let xor = bits ^ previous;
+            previous = bits;
+            if xor == 0 {
+                writer.write_bit(false);
+                continue;
+            }
+            writer.write_bit(true);
+            let leading = xor.leading_zeros();
+            let trailing = xor.trailing_zeros();
+            match window {
+                Some((w_leading, w_len))
+                    if leading >= w_leading && trailing >= 64 - w_leading - w_len =>
+                {
+                    writer.write_bit(false);
+                    writer.write_bits(xor >> (64 - w_leading - w_len), w_len);
+                }
+                _ => {
+                    let len = 64 - leading - trailing;
+                    writer.write_bit(true);
+                    writer.write_bits(leading as u64, 6);
+                    writer.write_bits((len - 1) as u64, 6);
+                    writer.write_bits(xor >> trailing, len);
+                    window = Some((leading, len));
+                }
+            }
+        }
+        self.data = writer.finish();
+        debug!(
+            "Compressed {} elements into {} bytes!",
+            data.len(),
+            self.data.len()
+        );
+    }
+
+    /// Receives a data stream and generates a Gorilla
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
+        let config = BinConfig::get();
+        let (gorilla, _) = bincode::decode_from_slice(data, config)?;
+        Ok(gorilla)
+    }
+
+    /// This function transforms the structure in a Binary stream to be appended to the frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        bincode::encode_to_vec(self, config).unwrap()
+    }
+
+    /// Decodes `frame_size` values out of the bit stream
+    pub fn to_data(&self, frame_size: usize) -> Result<Vec<f64>, Error> {
+        let mut reader = BitReader::new(&self.data);
+        let mut out = Vec::with_capacity(frame_size.min(self.data.len() * 8));
+        if frame_size == 0 {
+            return Ok(out);
+        }
+        let mut previous = reader.read_bits(64)?;
+        out.push(f64::from_bits(previous));
+        let mut window: Option<(u32, u32)> = None;
+        while out.len() < frame_size {
+            if reader.read_bit()? {
+                let (leading, len) = if reader.read_bit()? {
+                    let leading = reader.read_bits(6)? as u32;
+                    let len = reader.read_bits(6)? as u32 + 1;
+                    if leading + len > 64 {
+                        return Err(Error::CorruptData(format!(
+                            "gorilla window of {} bits after {} leading zeros",
+                            len, leading
+                        )));
+                    }
+                    window = Some((leading, len));
+                    (leading, len)
+                } else {
+                    window.ok_or_else(|| {
+                        Error::CorruptData("gorilla value reuses a missing window".to_string())
+                    })?
+                };
+                previous ^= reader.read_bits(len)? << (64 - leading - len);
+            }
+            out.push(f64::from_bits(previous));
+        }
+        Ok(out)
+    }
+}
+
+impl Default for Gorilla {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn gorilla(data: &[f64]) -> Vec<u8> {
+    info!("Initializing Gorilla Compressor");
+    let mut c = Gorilla::new();
+    c.compress(data);
+    c.to_bytes()
+}
+
+pub fn gorilla_to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    let c = Gorilla::decompress(compressed_data)?;
+    c.to_data(sample_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gorilla_constant() {
+        let vector1 = vec![1.0, 1.0, 1.0, 1.0, 1.0];
+        // 8 bytes for the first value and one bit for each repeated one
+        assert_eq!(gorilla(&vector1), [45, 9, 63, 240, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_gorilla_roundtrip() {
+        let vector1: Vec<f64> = (0..1000)
+            .map(|i| 1000.0 + (i as f64 / 50.0).sin() * 0.25 + (i / 7) as f64)
+            .chain([f64::MAX, f64::MIN_POSITIVE, -0.0, 0.0, 1.5])
+            .collect();
+        let compressed = gorilla(&vector1);
+        assert_eq!(
+            gorilla_to_data(vector1.len(), &compressed).unwrap(),
+            vector1
+        );
+        // Slow changing series take less than the raw values
+        let slow = vec![20.5, 20.5, 20.75, 20.75, 20.75, 21.0, 21.0, 20.75];
+        assert!(gorilla(&slow).len() < slow.len() * 8 / 2);
+        assert_eq!(gorilla_to_data(slow.len(), &gorilla(&slow)).unwrap(), slow);
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        let vector1 = vec![1.0, 2.0, 3.0];
+        let compressed = gorilla(&vector1);
+        assert!(gorilla_to_data(vector1.len(), &compressed[..compressed.len() - 1]).is_err());
+        assert!(matches!(
+            gorilla_to_data(vector1.len() + 10, &compressed),
+            Err(Error::TruncatedInput)
+        ));
+    }
+}
@@ -17,17 +17,36 @@ limitations under the License.
 use bincode::config::{self, Configuration};
 use bincode::{Decode, Encode};
 
+use crate::error::Error;
 use crate::optimizer::utils::DataStats;
 
+use self::chebyshev::{chebyshev, chebyshev_allowed_error, chebyshev_to_data};
 use self::constant::{constant_compressor, constant_to_data};
+use self::counter::{counter, counter_compressor, counter_to_data};
+use self::dct::{dct, dct_compressor, dct_to_data};
+use self::delta::{delta, delta_compressor, delta_to_data};
 use self::fft::{fft, fft_compressor, fft_to_data};
+use self::gorilla::{gorilla, gorilla_to_data};
 use self::noop::{noop, noop_to_data};
+use self::pla::{pla_allowed_error, pla_default, pla_to_data};
 use self::polynomial::{polynomial, polynomial_allowed_error, to_data, PolynomialType};
+use self::rle::{rle_compressor, rle_to_data};
+use self::seasonal::{seasonal, seasonal_allowed_error, seasonal_to_data};
+use self::wavelet::{wavelet, wavelet_compressor, wavelet_to_data};
 
+pub mod chebyshev;
 pub mod constant;
+pub mod counter;
+pub mod dct;
+pub mod delta;
 pub mod fft;
+pub mod gorilla;
 pub mod noop;
+pub mod pla;
 pub mod polynomial;
+pub mod rle;
+pub mod seasonal;
+pub mod wavelet;
 
 #[derive(Encode, Decode, Default, Debug, Clone, Copy, Eq, Hash, PartialEq)]
 pub enum Compressor {
@@ -38,6 +57,15 @@ pub enum Compressor {
     Constant,
     Polynomial,
     Auto,
+    Gorilla,
+    Delta,
+    Counter,
+    Rle,
+    Pla,
+    Dct,
+    Wavelet,
+    Chebyshev,
+    Seasonal,
 }
 
 /// Struct to store the results of a compression round. Will be used to pick the best compressor.
@@ -57,38 +85,59 @@ impl CompressorResult {
 }
 
 impl Compressor {
-    pub fn compress(&self, data: &[f64]) -> Vec<u8> {
+    /// Checks if the compressor fits a model to the data, what the model misses can be kept as residuals
+    pub fn supports_residuals(&self) -> bool {
+        matches!(
+            self,
+            Compressor::FFT
+                | Compressor::Dct
+                | Compressor::Polynomial
+                | Compressor::Idw
+                | Compressor::Wavelet
+                | Compressor::Chebyshev
+        )
+    }
+
+    pub fn compress(&self, data: &[f64]) -> Result<Vec<u8>, Error> {
+        if data.is_empty() {
+            return Err(Error::EmptyInput);
+        }
         let stats = DataStats::new(data);
-        match self {
+        Ok(match self {
             Compressor::Noop => noop(data),
             Compressor::FFT => fft(data),
             Compressor::Constant => constant_compressor(data, stats).compressed_data,
             Compressor::Polynomial => polynomial(data, PolynomialType::Polynomial),
             Compressor::Idw => polynomial(data, PolynomialType::Idw),
-            _ => todo!(),
-        }
+            Compressor::Gorilla => gorilla(data),
+            Compressor::Delta => delta(data),
+            Compressor::Counter => counter(data)?,
+            Compressor::Rle => rle_compressor(data, stats).compressed_data,
+            Compressor::Pla => pla_default(data),
+            Compressor::Dct => dct(data),
+            Compressor::Wavelet => wavelet(data),
+            Compressor::Chebyshev => chebyshev(data),
+            Compressor::Seasonal => seasonal(data),
+            Compressor::Auto => return Err(Error::UnsupportedCompressor(*self)),
+        })
     }
 
-    pub fn compress_bounded(&self, data: &[f64], max_error: f64) -> Vec<u8> {
-        let stats = DataStats::new(data);
-        match self {
-            Compressor::Noop => noop(data),
-            Compressor::FFT => fft_compressor(data, max_error, stats).compressed_data,
-            Compressor::Constant => constant_compressor(data, stats).compressed_data,
-            Compressor::Polynomial => {
-                polynomial_allowed_error(data, max_error, PolynomialType::Polynomial)
-                    .compressed_data
-            }
-            Compressor::Idw => {
-                polynomial_allowed_error(data, max_error, PolynomialType::Idw).compressed_data
-            }
-            _ => todo!(),
-        }
+    pub fn compress_bounded(&self, data: &[f64], max_error: f64) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .get_compress_bounded_results(data, max_error)?
+            .compressed_data)
     }
 
-    pub fn get_compress_bounded_results(&self, data: &[f64], max_error: f64) -> CompressorResult {
+    pub fn get_compress_bounded_results(
+        &self,
+        data: &[f64],
+        max_error: f64,
+    ) -> Result<CompressorResult, Error> {
+        if data.is_empty() {
+            return Err(Error::EmptyInput);
+        }
         let stats = DataStats::new(data);
-        match self {
+        Ok(match self {
             Compressor::Noop => CompressorResult::new(noop(data), 0.0),
             Compressor::FFT => fft_compressor(data, max_error, stats),
             Compressor::Constant => constant_compressor(data, stats),
@@ -96,18 +145,36 @@ impl Compressor {
                 polynomial_allowed_error(data, max_error, PolynomialType::Polynomial)
             }
             Compressor::Idw => polynomial_allowed_error(data, max_error, PolynomialType::Idw),
-            _ => todo!(),
-        }
+            Compressor::Gorilla => CompressorResult::new(gorilla(data), 0.0),
+            Compressor::Delta => delta_compressor(data, stats),
+            Compressor::Counter => counter_compressor(data)?,
+            Compressor::Rle => rle_compressor(data, stats),
+            Compressor::Pla => pla_allowed_error(data, max_error),
+            Compressor::Dct => dct_compressor(data, max_error, stats),
+            Compressor::Wavelet => wavelet_compressor(data, max_error),
+            Compressor::Chebyshev => chebyshev_allowed_error(data, max_error),
+            Compressor::Seasonal => seasonal_allowed_error(data, max_error),
+            Compressor::Auto => return Err(Error::UnsupportedCompressor(*self)),
+        })
     }
 
-    pub fn decompress(&self, samples: usize, data: &[u8]) -> Vec<f64> {
+    pub fn decompress(&self, samples: usize, data: &[u8]) -> Result<Vec<f64>, Error> {
         match self {
             Compressor::Noop => noop_to_data(samples, data),
             Compressor::FFT => fft_to_data(samples, data),
             Compressor::Constant => constant_to_data(samples, data),
             Compressor::Polynomial => to_data(samples, data),
             Compressor::Idw => to_data(samples, data),
-            _ => todo!(),
+            Compressor::Gorilla => gorilla_to_data(samples, data),
+            Compressor::Delta => delta_to_data(samples, data),
+            Compressor::Counter => counter_to_data(samples, data),
+            Compressor::Rle => rle_to_data(samples, data),
+            Compressor::Pla => pla_to_data(samples, data),
+            Compressor::Dct => dct_to_data(samples, data),
+            Compressor::Wavelet => wavelet_to_data(samples, data),
+            Compressor::Chebyshev => chebyshev_to_data(samples, data),
+            Compressor::Seasonal => seasonal_to_data(samples, data),
+            Compressor::Auto => Err(Error::UnsupportedCompressor(*self)),
         }
     }
 }
@@ -15,6 +15,7 @@ limitations under the License.
 */
 
 use super::BinConfig;
+use crate::error::Error;
 use bincode::{Decode, Encode};
 use log::{debug, info};
 
@@ -52,10 +53,10 @@ impl Noop {
     }
 
     /// Receives a data stream and generates a Noop
-    pub fn decompress(data: &[u8]) -> Self {
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
         let config = BinConfig::get();
-        let (noop, _) = bincode::decode_from_slice(data, config).unwrap();
-        noop
+        let (noop, _) = bincode::decode_from_slice(data, config)?;
+        Ok(noop)
     }
 
     /// This function transforms the structure in a Binary stream to be appended to the frame
@@ -76,10 +77,10 @@ pub fn noop(data: &[f64]) -> Vec<u8> {
     c.to_bytes()
 }
 
-pub fn noop_to_data(sample_number: usize, compressed_data: &[u8]) -> Vec<f64> {
-    let c = Noop::decompress(compressed_data);
+pub fn noop_to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    let c = Noop::decompress(compressed_data)?;
     let out_i64 = c.to_data(sample_number);
-    out_i64.iter().map(|&x| x as f64).collect()
+    Ok(out_i64.iter().map(|&x| x as f64).collect())
 }
 
 #[cfg(test)]
@@ -98,7 +99,7 @@ mod tests {
         let mut c = Noop::new(vector1.len());
         c.compress(&vector1);
         let bin_data = c.to_bytes();
-        let c2 = Noop::decompress(&bin_data);
+        let c2 = Noop::decompress(&bin_data).unwrap();
 
         assert_eq!(c.clone(), c2);
     }
@@ -107,7 +108,7 @@ mod tests {
     fn test_decompression() {
         let vector1 = vec![1.0, 2.0, 3.0, 4.0, 1.0];
         let n = noop(&vector1);
-        assert_eq!(noop_to_data(vector1.len(), &n), vector1);
+        assert_eq!(noop_to_data(vector1.len(), &n).unwrap(), vector1);
     }
 
     #[test]
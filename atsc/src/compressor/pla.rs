@@ -0,0 +1,230 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use super::{BinConfig, CompressorResult};
+use crate::error::Error;
+use crate::utils::error::error_mape_exact_zeros;
+use bincode::{Decode, Encode};
+use log::{debug, info};
+
+const PLA_COMPRESSOR_ID: u8 = 80;
+/// Relative tolerance used when no error is provided
+const DEFAULT_TOLERANCE: f64 = 0.01;
+
+/// How far each reconstructed sample is allowed to be from the original one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tolerance {
+    /// At most this distance
+    Absolute(f64),
+    /// At most this fraction of the original value (0.01 is 1%)
+    Relative(f64),
+}
+
+impl Tolerance {
+    fn of(&self, value: f64) -> f64 {
+        match self {
+            Tolerance::Absolute(tolerance) => *tolerance,
+            Tolerance::Relative(tolerance) => tolerance * value.abs(),
+        }
+    }
+}
+
+/// A line starting at a sample, the sample `k` positions later is `value + slope * k`
+#[derive(Encode, Decode, PartialEq, Debug, Clone, Copy)]
+pub struct Segment {
+    pub len: u64,
+    pub value: f64,
+    pub slope: f64,
+}
+
+/// Piecewise linear approximation, built with a swing filter.
+///
+/// Each segment starts exactly at a sample and is extended one sample at a time while there is a slope that keeps
+/// every sample of the segment within the tolerance. Unlike the other lossy compressors, that bound an average
+/// error, every single sample is guaranteed to be within the tolerance.
+#[derive(Encode, Decode, PartialEq, Debug, Clone)]
+pub struct Pla {
+    pub id: u8,
+    pub segments: Vec<Segment>,
+}
+
+impl Pla {
+    pub fn new() -> Self {
+        debug!("PLA compressor");
+        Pla {
+            id: PLA_COMPRESSOR_ID,
+            segments: Vec::new(),
+        }
+    }
+
+    pub fn compress(&mut self, data: &[f64], tolerance: Tolerance) {
+        let mut start = 0;
+        while start < data.len() {
+            let value = data[start];
+            // Range of slopes that keep every sample so far within the tolerance
+            let (mut low, mut high) = (f64::NEG_INFINITY, f64::INFINITY);
+            let mut end = start + 1;
+            while end < data.len() {
+                let k = (end - start) as f64;
+                let allowed = tolerance.of(data[end]);
+                let sample_low = (data[end] - allowed - value) / k;
+                let sample_high = (data[end] + allowed - value) / k;
+                if sample_low > high || sample_high < low {
+                    break;
+                }
+                low = low.max(sample_low);
+                high = high.min(sample_high);
+                end += 1;
+            }
+            let slope = if end - start > 1 {
+                (low + high) / 2.0
+            } else {
+                0.0
+            };
+            // Rounding can still push a sample out of the tolerance, the segment ends right before it
+            let len = (1..end - start)
+                .find(|&k| {
+                    let sample = data[start + k];
+                    (value + slope * k as f64 - sample).abs() > tolerance.of(sample)
+                })
+                .unwrap_or(end - start);
+            self.segments.push(Segment {
+                len: len as u64,
+                value,
+                slope,
+            });
+            start += len;
+        }
+        debug!(
+            "Compressed {} elements into {} segments!",
+            data.len(),
+            self.segments.len()
+        );
+    }
+
+    /// Receives a data stream and generates a Pla
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
+        let config = BinConfig::get();
+        let (pla, _) = bincode::decode_from_slice(data, config)?;
+        Ok(pla)
+    }
+
+    /// This function transforms the structure in a Binary stream to be appended to the frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        bincode::encode_to_vec(self, config).unwrap()
+    }
+
+    /// Draws the segments back into `frame_size` samples
+    pub fn to_data(&self, frame_size: usize) -> Result<Vec<f64>, Error> {
+        let mut data = Vec::with_capacity(frame_size);
+        for segment in self.segments.iter() {
+            if segment.len > (frame_size - data.len()) as u64 {
+                return Err(Error::CorruptData(format!(
+                    "segments hold more than the {} samples of the frame",
+                    frame_size
+                )));
+            }
+            data.extend((0..segment.len).map(|k| segment.value + segment.slope * k as f64));
+        }
+        Ok(data)
+    }
+}
+
+impl Default for Pla {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn pla(data: &[f64], tolerance: Tolerance) -> Vec<u8> {
+    info!("Initializing PLA Compressor");
+    let mut c = Pla::new();
+    c.compress(data, tolerance);
+    c.to_bytes()
+}
+
+/// Compresses the data with the default relative tolerance
+pub fn pla_default(data: &[f64]) -> Vec<u8> {
+    pla(data, Tolerance::Relative(DEFAULT_TOLERANCE))
+}
+
+/// Compresses the data keeping every sample within `max_error` of the original one, relative to its value
+pub fn pla_allowed_error(data: &[f64], max_error: f64) -> CompressorResult {
+    let mut c = Pla::new();
+    c.compress(data, Tolerance::Relative(max_error));
+    let decompressed = c.to_data(data.len()).unwrap();
+    let error = error_mape_exact_zeros(data, &decompressed);
+    CompressorResult::new(c.to_bytes(), error)
+}
+
+pub fn pla_to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    let c = Pla::decompress(compressed_data)?;
+    c.to_data(sample_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_bound(data: &[f64], tolerance: Tolerance) -> usize {
+        let mut c = Pla::new();
+        c.compress(data, tolerance);
+        let out = pla_to_data(data.len(), &c.to_bytes()).unwrap();
+        assert_eq!(out.len(), data.len());
+        for (original, decompressed) in data.iter().zip(out.iter()) {
+            assert!((original - decompressed).abs() <= tolerance.of(*original));
+        }
+        c.segments.len()
+    }
+
+    #[test]
+    fn test_pla_lines() {
+        let vector1: Vec<f64> = (0..100)
+            .map(|i| {
+                if i < 50 {
+                    i as f64 * 2.0
+                } else {
+                    100.0 - i as f64
+                }
+            })
+            .collect();
+        assert_eq!(check_bound(&vector1, Tolerance::Absolute(0.0)), 2);
+    }
+
+    #[test]
+    fn test_pla_bound() {
+        let vector1: Vec<f64> = (0..2000)
+            .map(|i| 500.0 + (i as f64 / 40.0).sin() * 100.0 + ((i * 7919) % 13) as f64)
+            .collect();
+        let absolute = check_bound(&vector1, Tolerance::Absolute(5.0));
+        let relative = check_bound(&vector1, Tolerance::Relative(0.05));
+        assert!(absolute < 2000 / 4);
+        assert!(relative < absolute);
+        // A spike is kept, instead of being averaged away
+        let mut vector2 = vec![10.0; 500];
+        vector2[250] = 1000.0;
+        assert_eq!(check_bound(&vector2, Tolerance::Relative(0.05)), 3);
+    }
+
+    #[test]
+    fn test_pla_allowed_error() {
+        let vector1 = vec![1.0, 1.01, 1.02, 1.5, 1.49, 0.0, 0.0, -3.0];
+        let result = pla_allowed_error(&vector1, 0.05);
+        assert!(result.error <= 0.05);
+        assert!(pla_to_data(3, &result.compressed_data).is_err());
+    }
+}
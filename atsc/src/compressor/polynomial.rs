@@ -14,6 +14,7 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::error::Error;
 use crate::optimizer::utils::{Bitdepth, DataStats};
 use crate::utils::{error::calculate_error, round_and_limit_f64, round_f64, DECIMAL_PRECISION};
 
@@ -22,6 +23,7 @@ use bincode::{Decode, Encode};
 use inverse_distance_weight::IDW;
 use log::{debug, info, trace};
 use splines::{Interpolation, Key, Spline};
+use std::collections::BinaryHeap;
 
 const POLYNOMIAL_COMPRESSOR_ID: u8 = 0;
 const IDW_COMPRESSOR_ID: u8 = 1;
@@ -46,7 +48,10 @@ pub struct Polynomial {
     pub data_points: Vec<f64>,
     pub min: f64,
     pub max: f64,
+    /// Distance between evenly spaced points, 0 when the points are at the `knots`
     pub point_step: u8,
+    /// Distance from each point to the next one, for points that are not evenly spaced
+    pub knots: Vec<u64>,
     pub error: Option<f64>,
     pub bitdepth: Bitdepth,
 }
@@ -82,6 +87,9 @@ impl Encode for Polynomial {
         Encode::encode(&self.min, encoder)?;
         Encode::encode(&self.max, encoder)?;
         Encode::encode(&self.point_step, encoder)?;
+        if self.point_step == 0 {
+            Encode::encode(&self.knots, encoder)?;
+        }
         Ok(())
     }
 }
@@ -117,6 +125,11 @@ impl Decode for Polynomial {
         let min = Decode::decode(decoder)?;
         let max = Decode::decode(decoder)?;
         let point_step = Decode::decode(decoder)?;
+        let knots = if point_step == 0 {
+            Decode::decode(decoder)?
+        } else {
+            Vec::new()
+        };
 
         Ok(Self {
             id,
@@ -125,6 +138,7 @@ impl Decode for Polynomial {
             min,
             max,
             point_step,
+            knots,
             error: None,
         })
     }
@@ -162,6 +176,11 @@ impl<'__de> ::bincode::BorrowDecode<'__de> for Polynomial {
         let min = Decode::decode(decoder)?;
         let max = Decode::decode(decoder)?;
         let point_step = Decode::decode(decoder)?;
+        let knots = if point_step == 0 {
+            Decode::decode(decoder)?
+        } else {
+            Vec::new()
+        };
 
         Ok(Self {
             id,
@@ -170,6 +189,7 @@ impl<'__de> ::bincode::BorrowDecode<'__de> for Polynomial {
             min,
             max,
             point_step,
+            knots,
             error: None,
         })
     }
@@ -194,6 +214,7 @@ impl Polynomial {
             data_points: Vec::with_capacity(sample_count),
             // Minimum step is always 1
             point_step: 1,
+            knots: Vec::new(),
             error: None,
             bitdepth,
         }
@@ -301,7 +322,68 @@ impl Polynomial {
         debug!("Compressed Hinted Values: {:?}", values);
 
         self.data_points = values;
-        self.point_step = step as u8;
+        // Steps that don't fit in the u8 are stored as knots
+        match u8::try_from(step) {
+            Ok(step) => {
+                self.point_step = step;
+                self.knots.clear();
+            }
+            Err(_) => self.set_knots(&points.iter().map(|&f| f as usize).collect::<Vec<_>>()),
+        }
+    }
+
+    /// Stores the positions of the points as the distance from each one to the next, the first one is always 0
+    fn set_knots(&mut self, positions: &[usize]) {
+        self.point_step = 0;
+        self.knots = positions.windows(2).map(|w| (w[1] - w[0]) as u64).collect();
+    }
+
+    /// Places the points where the data bends the most instead of evenly, so smooth stretches take a couple of
+    /// points and sharp features get as many as they need. Points are ranked as a Douglas-Peucker split would pick
+    /// them, and the fewest of them that are within `max_err` are kept.
+    pub fn compress_adaptive(&mut self, data: &[f64], max_err: f64) {
+        if self.max == self.min {
+            debug!("Same max and min, we're done here!");
+            return;
+        }
+        let data_len = data.len();
+        if data_len < 3 {
+            self.compress_hinted(data, data_len);
+            self.error = Some(0.0);
+            return;
+        }
+        let method = self.get_method();
+        let order = knot_order(data, (data_len / 10).max(1));
+        let target_error = round_f64(max_err, 3);
+        let mut keep = |count: usize| {
+            let mut positions = order[..count].to_vec();
+            positions.extend([0, data_len - 1]);
+            positions.sort_unstable();
+            self.data_points = positions.iter().map(|&p| data[p]).collect();
+            self.set_knots(&positions);
+            let out_data = match method {
+                Method::CatmullRom => self.polynomial_to_data(data_len),
+                Method::Idw => self.idw_to_data(data_len),
+            };
+            calculate_error(data, &out_data)
+        };
+        // The error mostly goes down as points are added, the count is found by bisection
+        let (mut low, mut high) = (0, order.len());
+        while low < high {
+            let count = (low + high) / 2;
+            if round_f64(keep(count), 4) <= target_error {
+                high = count;
+            } else {
+                low = count + 1;
+            }
+        }
+        let current_err = keep(low);
+        self.error = Some(current_err);
+        debug!(
+            "Final Stored Data Length: {} Knots ranked: {}",
+            self.data_points.len(),
+            order.len()
+        );
     }
 
     pub fn compress(&mut self, data: &[f64]) {
@@ -313,10 +395,10 @@ impl Polynomial {
         self.compress_hinted(data, points)
     }
 
-    pub fn decompress(data: &[u8]) -> Self {
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
         let config = BinConfig::get();
-        let (poly, _) = bincode::decode_from_slice(data, config).unwrap();
-        poly
+        let (poly, _) = bincode::decode_from_slice(data, config)?;
+        Ok(poly)
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
@@ -327,6 +409,15 @@ impl Polynomial {
     /// Since IDW and Polynomial are the same code everywhere, this function prepares the data
     /// to be used by one of the polynomial decompression methods
     fn get_positions(&self, frame_size: usize) -> Vec<usize> {
+        if self.point_step == 0 {
+            let mut position = 0;
+            let mut points = vec![position];
+            for &knot in self.knots.iter() {
+                position += knot as usize;
+                points.push(position);
+            }
+            return points;
+        }
         let mut points = Vec::with_capacity(frame_size);
         for position_value in (0..frame_size).step_by(self.point_step as usize) {
             points.push(position_value);
@@ -392,15 +483,32 @@ impl Polynomial {
             .collect()
     }
 
-    pub fn to_data(&self, frame_size: usize) -> Vec<f64> {
+    pub fn to_data(&self, frame_size: usize) -> Result<Vec<f64>, Error> {
         if self.max == self.min {
             debug!("Same max and min, faster decompression!");
-            return vec![self.max; frame_size];
+            return Ok(vec![self.max; frame_size]);
         }
-        match self.id {
+        if frame_size == 0 {
+            return Err(Error::CorruptData(
+                "Polynomial points don't match the frame size".to_string(),
+            ));
+        }
+        let knots_fit = self.point_step != 0
+            || (self.knots.iter().all(|&knot| knot > 0)
+                && self
+                    .knots
+                    .iter()
+                    .try_fold(0u64, |sum, &knot| sum.checked_add(knot))
+                    == Some(frame_size as u64 - 1));
+        if !knots_fit || self.get_positions(frame_size).len() != self.data_points.len() {
+            return Err(Error::CorruptData(
+                "Polynomial points don't match the frame size".to_string(),
+            ));
+        }
+        Ok(match self.id {
             PolynomialType::Idw => self.idw_to_data(frame_size),
             PolynomialType::Polynomial => self.polynomial_to_data(frame_size),
-        }
+        })
     }
 }
 
@@ -412,6 +520,35 @@ pub fn polynomial(data: &[f64], p_type: PolynomialType) -> Vec<u8> {
     c.to_bytes()
 }
 
+/// Interior positions of the data, in the order a Douglas-Peucker split picks them: the farthest from the line
+/// between the points around it first. Stops at `limit` positions.
+fn knot_order(data: &[f64], limit: usize) -> Vec<usize> {
+    // Farthest position between `start` and `end`, with its distance as ordered bits (it is never negative)
+    let farthest = |start: usize, end: usize| {
+        let slope = (data[end] - data[start]) / (end - start) as f64;
+        (start + 1..end)
+            .map(|i| {
+                let distance = (data[i] - data[start] - slope * (i - start) as f64).abs();
+                (distance.to_bits(), i, start, end)
+            })
+            .max()
+    };
+    let mut heap = BinaryHeap::new();
+    heap.extend(farthest(0, data.len() - 1));
+    let mut order = Vec::with_capacity(limit);
+    while order.len() < limit {
+        let Some((_, split, start, end)) = heap.pop() else {
+            break;
+        };
+        order.push(split);
+        heap.extend(farthest(start, split));
+        heap.extend(farthest(split, end));
+    }
+    order
+}
+
+/// Compresses targeting a specific max error allowed, with evenly spaced points and with adaptive ones. The
+/// smallest result is kept.
 pub fn polynomial_allowed_error(
     data: &[f64],
     allowed_error: f64,
@@ -419,13 +556,32 @@ pub fn polynomial_allowed_error(
 ) -> CompressorResult {
     info!("Initializing Polynomial Compressor");
     let stats = DataStats::new(data);
-    let mut c = Polynomial::new(data.len(), stats.min, stats.max, p_type, stats.bitdepth);
-    c.compress_bounded(data, allowed_error);
-    CompressorResult::new(c.to_bytes(), c.error.unwrap_or(0.0))
+    let mut uniform = Polynomial::new(
+        data.len(),
+        stats.min,
+        stats.max,
+        p_type.clone(),
+        stats.bitdepth,
+    );
+    uniform.compress_bounded(data, allowed_error);
+    let mut adaptive = Polynomial::new(data.len(), stats.min, stats.max, p_type, stats.bitdepth);
+    adaptive.compress_adaptive(data, allowed_error);
+    // Same precision as the error control loop of `compress_bounded`
+    let target_error = round_f64(allowed_error, 3);
+    [uniform, adaptive]
+        .into_iter()
+        .map(|c| CompressorResult::new(c.to_bytes(), c.error.unwrap_or(0.0)))
+        .min_by_key(|r| {
+            (
+                round_f64(r.error, 4) > target_error,
+                r.compressed_data.len(),
+            )
+        })
+        .unwrap()
 }
 
-pub fn to_data(sample_number: usize, compressed_data: &[u8]) -> Vec<f64> {
-    let c = Polynomial::decompress(compressed_data);
+pub fn to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    let c = Polynomial::decompress(compressed_data)?;
     c.to_data(sample_number)
 }
 
@@ -489,7 +645,10 @@ mod tests {
         ];
         let frame_size = vector1.len();
         let idw_data = polynomial(&vector1, PolynomialType::Polynomial);
-        let out = Polynomial::decompress(&idw_data).to_data(frame_size);
+        let out = Polynomial::decompress(&idw_data)
+            .unwrap()
+            .to_data(frame_size)
+            .unwrap();
         assert_eq!(
             out,
             [
@@ -506,7 +665,10 @@ mod tests {
         ];
         let frame_size = vector1.len();
         let idw_data = polynomial(&vector1, PolynomialType::Polynomial);
-        let out = Polynomial::decompress(&idw_data).to_data(frame_size);
+        let out = Polynomial::decompress(&idw_data)
+            .unwrap()
+            .to_data(frame_size)
+            .unwrap();
         assert_eq!(
             out,
             [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0]
@@ -520,7 +682,10 @@ mod tests {
         ];
         let frame_size = vector1.len();
         let cr = polynomial_allowed_error(&vector1, 0.05, PolynomialType::Polynomial);
-        let out = Polynomial::decompress(&cr.compressed_data).to_data(frame_size);
+        let out = Polynomial::decompress(&cr.compressed_data)
+            .unwrap()
+            .to_data(frame_size)
+            .unwrap();
         let e = calculate_error(&vector1, &out);
         assert!(e <= 0.05);
     }
@@ -541,7 +706,10 @@ mod tests {
         ];
         let frame_size = vector1.len();
         let idw_data = polynomial(&vector1, PolynomialType::Idw);
-        let out = Polynomial::decompress(&idw_data).to_data(frame_size);
+        let out = Polynomial::decompress(&idw_data)
+            .unwrap()
+            .to_data(frame_size)
+            .unwrap();
         assert_eq!(
             out,
             [
@@ -558,7 +726,10 @@ mod tests {
         ];
         let frame_size = vector1.len();
         let idw_data = polynomial(&vector1, PolynomialType::Idw);
-        let out = Polynomial::decompress(&idw_data).to_data(frame_size);
+        let out = Polynomial::decompress(&idw_data)
+            .unwrap()
+            .to_data(frame_size)
+            .unwrap();
         assert_eq!(
             out,
             [
@@ -575,11 +746,83 @@ mod tests {
         ];
         let frame_size = vector1.len();
         let cr = polynomial_allowed_error(&vector1, 0.02, PolynomialType::Idw);
-        let out = Polynomial::decompress(&cr.compressed_data).to_data(frame_size);
+        let out = Polynomial::decompress(&cr.compressed_data)
+            .unwrap()
+            .to_data(frame_size)
+            .unwrap();
         let e = calculate_error(&vector1, &out);
         assert!(e <= 0.02);
     }
 
+    #[test]
+    fn test_adaptive_knots() {
+        // Slow drift with a couple of sharp steps, evenly spaced points waste most of them on the drift
+        let vector1: Vec<f64> = (0..2000)
+            .map(|i| {
+                let step = if (700..760).contains(&i) || i > 1500 {
+                    80.0
+                } else {
+                    0.0
+                };
+                100.0 + (i as f64 / 400.0).sin() * 5.0 + step
+            })
+            .collect();
+        for p_type in [PolynomialType::Polynomial, PolynomialType::Idw] {
+            let stats = DataStats::new(&vector1);
+            let mut uniform = Polynomial::new(
+                vector1.len(),
+                stats.min,
+                stats.max,
+                p_type.clone(),
+                stats.bitdepth,
+            );
+            uniform.compress_bounded(&vector1, 0.01);
+            let mut c = Polynomial::new(
+                vector1.len(),
+                stats.min,
+                stats.max,
+                p_type.clone(),
+                stats.bitdepth,
+            );
+            c.compress_adaptive(&vector1, 0.01);
+            assert_eq!(c.point_step, 0);
+            // IDW spreads every point over the whole frame, it doesn't gain as much from the placement
+            let factor = match p_type {
+                PolynomialType::Polynomial => 4,
+                PolynomialType::Idw => 1,
+            };
+            assert!(c.data_points.len() * factor < uniform.data_points.len());
+            let out = to_data(vector1.len(), &c.to_bytes()).unwrap();
+            assert!(calculate_error(&vector1, &out) <= 0.01);
+            assert_eq!(calculate_error(&vector1, &out), c.error.unwrap());
+            let cr = polynomial_allowed_error(&vector1, 0.01, p_type);
+            assert_eq!(cr.compressed_data, c.to_bytes());
+        }
+    }
+
+    #[test]
+    fn test_wide_point_step() {
+        // A step over 255 used to wrap around in the u8
+        let vector1: Vec<f64> = (0..1000).map(|i| i as f64).collect();
+        let stats = DataStats::new(&vector1);
+        let mut c = Polynomial::new(
+            vector1.len(),
+            stats.min,
+            stats.max,
+            PolynomialType::Polynomial,
+            stats.bitdepth,
+        );
+        c.compress_hinted(&vector1, 3);
+        assert_eq!(c.point_step, 0);
+        assert_eq!(c.knots, [333, 333, 333]);
+        assert_eq!(to_data(vector1.len(), &c.to_bytes()).unwrap(), vector1);
+        // Knots that don't end at the last sample of the frame
+        assert!(to_data(vector1.len() - 1, &c.to_bytes()).is_err());
+        assert!(to_data(0, &c.to_bytes()).is_err());
+        c.knots = vec![333, 0, 666];
+        assert!(to_data(vector1.len(), &c.to_bytes()).is_err());
+    }
+
     #[test]
     fn test_line_polynomial() {
         let vector1 = vec![1.0, 1.0, 1.0, 1.0];
@@ -0,0 +1,199 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::{
+    compressor::CompressorResult,
+    error::Error,
+    optimizer::utils::{Bitdepth, DataStats},
+};
+
+use super::BinConfig;
+use bincode::{Decode, Encode};
+use log::debug;
+
+const RLE_COMPRESSOR_ID: u8 = 35;
+
+/// Compressor frame for step functions (replica counts, up/down status, ...). Stores each value once, with the
+/// number of samples it holds for.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Rle {
+    pub id: u8,
+    /// (value, run length)
+    pub runs: Vec<(f64, u64)>,
+    // For internal use only
+    bitdepth: Bitdepth,
+}
+
+impl Encode for Rle {
+    fn encode<__E: ::bincode::enc::Encoder>(
+        &self,
+        encoder: &mut __E,
+    ) -> Result<(), ::bincode::error::EncodeError> {
+        Encode::encode(&self.id, encoder)?;
+        Encode::encode(&self.bitdepth, encoder)?;
+        Encode::encode(&(self.runs.len() as u64), encoder)?;
+        for (value, len) in self.runs.iter() {
+            match &self.bitdepth {
+                Bitdepth::U8 => Encode::encode(&(*value as u8), encoder)?,
+                Bitdepth::I16 => Encode::encode(&(*value as i16), encoder)?,
+                Bitdepth::I32 => Encode::encode(&(*value as i32), encoder)?,
+                Bitdepth::F64 => Encode::encode(value, encoder)?,
+            }
+            Encode::encode(len, encoder)?;
+        }
+        Ok(())
+    }
+}
+
+impl Decode for Rle {
+    fn decode<__D: ::bincode::de::Decoder>(
+        decoder: &mut __D,
+    ) -> Result<Self, ::bincode::error::DecodeError> {
+        let id = Decode::decode(decoder)?;
+        let bitdepth = Decode::decode(decoder)?;
+        let count: u64 = Decode::decode(decoder)?;
+        let mut runs = Vec::new();
+        for _ in 0..count {
+            let value = match bitdepth {
+                Bitdepth::U8 => {
+                    let value: u8 = Decode::decode(decoder)?;
+                    value as f64
+                }
+                Bitdepth::I16 => {
+                    let value: i16 = Decode::decode(decoder)?;
+                    value as f64
+                }
+                Bitdepth::I32 => {
+                    let value: i32 = Decode::decode(decoder)?;
+                    value as f64
+                }
+                Bitdepth::F64 => Decode::decode(decoder)?,
+            };
+            runs.push((value, Decode::decode(decoder)?));
+        }
+        Ok(Self { id, runs, bitdepth })
+    }
+}
+
+/// Number of runs of repeated values in the data
+pub fn run_count(data: &[f64]) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+    1 + data.windows(2).filter(|w| w[0] != w[1]).count()
+}
+
+impl Rle {
+    pub fn new(bitdepth: Bitdepth) -> Self {
+        debug!("RLE compressor");
+        Rle {
+            id: RLE_COMPRESSOR_ID,
+            runs: Vec::new(),
+            bitdepth,
+        }
+    }
+
+    pub fn compress(&mut self, data: &[f64]) {
+        for &value in data {
+            match self.runs.last_mut() {
+                Some((run_value, len)) if *run_value == value => *len += 1,
+                _ => self.runs.push((value, 1)),
+            }
+        }
+        debug!(
+            "Compressed {} elements into {} runs!",
+            data.len(),
+            self.runs.len()
+        );
+    }
+
+    /// Receives a data stream and generates a Rle
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
+        let config = BinConfig::get();
+        let (rle, _) = bincode::decode_from_slice(data, config)?;
+        Ok(rle)
+    }
+
+    /// This function transforms the structure into a Binary stream
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        bincode::encode_to_vec(self, config).unwrap()
+    }
+
+    /// Expands the runs back into `frame_size` samples
+    pub fn to_data(&self, frame_size: usize) -> Result<Vec<f64>, Error> {
+        let mut data = Vec::with_capacity(frame_size);
+        for &(value, len) in self.runs.iter() {
+            if len > (frame_size - data.len()) as u64 {
+                return Err(Error::CorruptData(format!(
+                    "runs hold more than the {} samples of the frame",
+                    frame_size
+                )));
+            }
+            data.resize(data.len() + len as usize, value);
+        }
+        Ok(data)
+    }
+}
+
+pub fn rle_compressor(data: &[f64], stats: DataStats) -> CompressorResult {
+    debug!("Initializing RLE Compressor. Error and Stats provided");
+    let mut c = Rle::new(stats.bitdepth);
+    c.compress(data);
+    CompressorResult::new(c.to_bytes(), 0.0)
+}
+
+pub fn rle_to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    let c = Rle::decompress(compressed_data)?;
+    c.to_data(sample_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rle_u8() {
+        let vector1 = vec![1.0, 1.0, 1.0, 3.0, 3.0, 1.0];
+        let stats = DataStats::new(&vector1);
+        let c = rle_compressor(&vector1, stats).compressed_data;
+        assert_eq!(c, [35, 3, 3, 1, 3, 3, 2, 1, 1]);
+        assert_eq!(rle_to_data(vector1.len(), &c).unwrap(), vector1);
+    }
+
+    #[test]
+    fn test_rle_f64() {
+        let mut vector1 = vec![0.5; 3000];
+        vector1.extend(vec![-1234.25; 5000]);
+        let stats = DataStats::new(&vector1);
+        let c = rle_compressor(&vector1, stats).compressed_data;
+        assert_eq!(c.len(), 1 + 1 + 1 + (8 + 3) * 2);
+        assert_eq!(rle_to_data(vector1.len(), &c).unwrap(), vector1);
+        assert_eq!(run_count(&vector1), 2);
+    }
+
+    #[test]
+    fn test_corrupted_runs() {
+        let vector1 = vec![1.0, 1.0, 2.0];
+        let stats = DataStats::new(&vector1);
+        let c = rle_compressor(&vector1, stats).compressed_data;
+        assert!(rle_to_data(2, &c).is_err());
+        assert!(matches!(
+            rle_to_data(3, &c[..c.len() - 1]),
+            Err(Error::TruncatedInput)
+        ));
+    }
+}
@@ -0,0 +1,433 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use super::chebyshev::{evaluate, fit};
+use super::fft::dominant_period;
+use super::{BinConfig, CompressorResult};
+use crate::error::Error;
+use crate::utils::{error::error_mape_exact_zeros, round_f64, DECIMAL_PRECISION};
+use bincode::{Decode, Encode};
+use log::{debug, info, trace};
+
+const SEASONAL_COMPRESSOR_ID: u8 = 110;
+/// Highest degree tried for the trend, anything faster than that is left to the season and the corrections
+const MAX_TREND_DEGREE: usize = 3;
+/// Fewest points kept for a season, coarser profiles are tried by halving the points from one per sample
+const MIN_PROFILE_POINTS: usize = 4;
+/// Times the trend and the season are fitted, each one on what the other one leaves
+const ROUNDS: usize = 2;
+/// Share of the error budget the corrections aim for
+const ERROR_MARGIN: f64 = 0.999_999;
+/// Error used when no error is provided
+const DEFAULT_ERROR: f64 = 0.01;
+
+/// A sample the model misses by more than the error, stored as it is
+#[derive(Encode, Decode, PartialEq, Debug, Clone, Copy)]
+pub struct Correction {
+    /// Distance to the previous correction, or position in the frame for the first one
+    pub gap: u64,
+    pub value: f64,
+}
+
+/// Seasonal-trend decomposition, for series repeating a pattern over a trend (daily traffic, weekly load, ...).
+///
+/// The frame is split in a trend, a Chebyshev series of a low degree over the whole frame, and a season, the
+/// average shape of one period of what the trend leaves. The season is stored at a reduced resolution, as points
+/// evenly spread over the period that are linearly interpolated. The samples the model misses the most are stored as
+/// they are, as many as needed for the error to be within the budget. The trend degree and the season resolution are
+/// the ones that take the fewest bytes, corrections included.
+#[derive(Encode, Decode, PartialEq, Debug, Clone)]
+pub struct Seasonal {
+    pub id: u8,
+    /// Samples in a period of the season
+    pub period: u64,
+    /// Chebyshev coefficients of the trend
+    pub trend: Vec<f64>,
+    /// Points of the season, empty if the frame has no season
+    pub profile: Vec<f64>,
+    pub corrections: Vec<Correction>,
+}
+
+/// Value of the season at the sample `i`, interpolated between the points of `profile`
+fn season_at(profile: &[f64], period: usize, i: usize) -> f64 {
+    if profile.is_empty() {
+        return 0.0;
+    }
+    let position = (i % period) as f64 * profile.len() as f64 / period as f64;
+    let point = position as usize;
+    let t = position - point as f64;
+    profile[point] * (1.0 - t) + profile[(point + 1) % profile.len()] * t
+}
+
+/// Fits a trend of `degree` and a season of `period` samples (one value per sample, centered on 0) to `data`.
+/// A period under 2 fits the trend alone.
+fn decompose(data: &[f64], period: usize, degree: usize) -> Option<(Vec<f64>, Vec<f64>)> {
+    let period = if period < 2 { 0 } else { period };
+    let mut season = vec![0.0; period];
+    let mut trend = Vec::new();
+    for _ in 0..ROUNDS {
+        let deseasoned: Vec<f64> = data
+            .iter()
+            .enumerate()
+            .map(|(i, value)| value - season.get(i % period.max(1)).unwrap_or(&0.0))
+            .collect();
+        trend = fit(&deseasoned, degree)?;
+        if period == 0 {
+            break;
+        }
+        let fitted = evaluate(&trend, data.len());
+        let mut sums = vec![0.0; period];
+        let mut counts = vec![0usize; period];
+        for (i, (value, trend)) in data.iter().zip(fitted.iter()).enumerate() {
+            sums[i % period] += value - trend;
+            counts[i % period] += 1;
+        }
+        season = sums
+            .iter()
+            .zip(counts.iter())
+            .map(|(sum, &count)| sum / count.max(1) as f64)
+            .collect();
+        // The level belongs to the trend
+        let mean = season.iter().sum::<f64>() / period as f64;
+        season.iter_mut().for_each(|value| *value -= mean);
+    }
+    Some((trend, season))
+}
+
+/// Averages a season of one value per sample into `points` values
+fn reduce(season: &[f64], points: usize) -> Vec<f64> {
+    let period = season.len();
+    if points == 0 || points == period {
+        return season[..points].to_vec();
+    }
+    let mut sums = vec![0.0; points];
+    let mut counts = vec![0usize; points];
+    for (phase, value) in season.iter().enumerate() {
+        // Nearest point, the last phases wrap around to the first one
+        let point = (phase * points + period / 2) / period % points;
+        sums[point] += value;
+        counts[point] += 1;
+    }
+    sums.iter()
+        .zip(counts.iter())
+        .map(|(sum, &count)| round_f64(sum / count.max(1) as f64, DECIMAL_PRECISION))
+        .collect()
+}
+
+impl Seasonal {
+    pub fn new() -> Self {
+        debug!("Seasonal compressor");
+        Seasonal {
+            id: SEASONAL_COMPRESSOR_ID,
+            period: 0,
+            trend: Vec::new(),
+            profile: Vec::new(),
+            corrections: Vec::new(),
+        }
+    }
+
+    /// Trend and season over `len` samples
+    fn model(&self, len: usize) -> Vec<f64> {
+        evaluate(&self.trend, len)
+            .iter()
+            .enumerate()
+            .map(|(i, trend)| {
+                let season = season_at(&self.profile, self.period as usize, i);
+                round_f64(trend + season, DECIMAL_PRECISION)
+            })
+            .collect()
+    }
+
+    /// Keeps the samples of `data` the model misses the most, as they are, until the error of the others is within
+    /// `max_error`
+    fn correct(&mut self, data: &[f64], max_error: f64) {
+        let model = self.model(data.len());
+        // Relative miss of every sample, samples the model should have matched exactly can't be missed at all
+        let mut misses: Vec<(f64, usize)> = data
+            .iter()
+            .zip(model.iter())
+            .enumerate()
+            .filter(|(_, (value, modelled))| value != modelled)
+            .map(|(i, (value, modelled))| {
+                let miss = ((modelled - value) / value).abs();
+                (
+                    if miss.is_finite() {
+                        miss
+                    } else {
+                        f64::INFINITY
+                    },
+                    i,
+                )
+            })
+            .collect();
+        misses.sort_by(|a, b| b.0.total_cmp(&a.0));
+        // Some slack, so the error summed in another order doesn't go over the budget
+        let budget = max_error * data.len() as f64 * ERROR_MARGIN;
+        let mut remaining: f64 = misses
+            .iter()
+            .map(|(miss, _)| miss)
+            .filter(|miss| miss.is_finite())
+            .sum();
+        let mut positions = Vec::new();
+        for &(miss, i) in misses.iter() {
+            if miss.is_finite() {
+                if remaining <= budget {
+                    break;
+                }
+                remaining -= miss;
+            }
+            positions.push(i);
+        }
+        positions.sort_unstable();
+        let mut last = 0;
+        self.corrections = positions
+            .into_iter()
+            .map(|i| {
+                let gap = (i - last) as u64;
+                last = i;
+                Correction {
+                    gap,
+                    value: data[i],
+                }
+            })
+            .collect();
+    }
+
+    /// Decomposes the data with the given period, or with the one detected in the data if there's no usable hint,
+    /// within `max_error`
+    pub fn compress_bounded(&mut self, data: &[f64], max_error: f64, period: Option<usize>) {
+        let period = period
+            .filter(|&period| period >= 2 && period <= data.len() / 2)
+            .or_else(|| dominant_period(data))
+            .unwrap_or(0);
+        let config = BinConfig::get();
+        let mut best: Option<(usize, Seasonal)> = None;
+        for degree in 0..=MAX_TREND_DEGREE.min(data.len().saturating_sub(1)) {
+            let Some((trend, season)) = decompose(data, period, degree) else {
+                continue;
+            };
+            // Every resolution of the season, from one point per sample, and no season at all
+            let mut resolutions: Vec<usize> = std::iter::successors(Some(season.len()), |points| {
+                Some(points / 2).filter(|&points| points >= MIN_PROFILE_POINTS)
+            })
+            .filter(|&points| points > 0)
+            .collect();
+            resolutions.push(0);
+            for points in resolutions {
+                let mut candidate = Seasonal {
+                    id: SEASONAL_COMPRESSOR_ID,
+                    period: if points == 0 { 0 } else { period as u64 },
+                    trend: trend.clone(),
+                    profile: reduce(&season, points),
+                    corrections: Vec::new(),
+                };
+                candidate.correct(data, max_error);
+                let size = bincode::encode_to_vec(&candidate, config).unwrap().len();
+                trace!(
+                    "Degree {}, {} season points: {} corrections, {} bytes",
+                    degree,
+                    points,
+                    candidate.corrections.len(),
+                    size
+                );
+                if best
+                    .as_ref()
+                    .map_or(true, |(best_size, _)| size < *best_size)
+                {
+                    best = Some((size, candidate));
+                }
+            }
+        }
+        if let Some((_, best)) = best {
+            *self = best;
+        }
+        debug!(
+            "Compressed {} elements, period {}, {} season points, {} corrections",
+            data.len(),
+            self.period,
+            self.profile.len(),
+            self.corrections.len()
+        );
+    }
+
+    /// Receives a data stream and generates a Seasonal
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
+        let config = BinConfig::get();
+        let (seasonal, _) = bincode::decode_from_slice(data, config)?;
+        Ok(seasonal)
+    }
+
+    /// This function transforms the structure in a Binary stream to be appended to the frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        bincode::encode_to_vec(self, config).unwrap()
+    }
+
+    /// Rebuilds the trend and the season over `frame_size` samples, and puts the corrections back
+    pub fn to_data(&self, frame_size: usize) -> Result<Vec<f64>, Error> {
+        if self.profile.len() as u64 > self.period {
+            return Err(Error::CorruptData(format!(
+                "season of {} points over a period of {} samples",
+                self.profile.len(),
+                self.period
+            )));
+        }
+        let mut data = self.model(frame_size);
+        let mut position = 0u64;
+        for (i, correction) in self.corrections.iter().enumerate() {
+            position = position
+                .checked_add(correction.gap)
+                .filter(|&position| position < frame_size as u64 && (i == 0 || correction.gap > 0))
+                .ok_or(Error::CorruptData(format!(
+                    "correction out of the {} samples of the frame",
+                    frame_size
+                )))?;
+            data[position as usize] = correction.value;
+        }
+        Ok(data)
+    }
+}
+
+impl Default for Seasonal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compresses the data with the default error and the detected period
+pub fn seasonal(data: &[f64]) -> Vec<u8> {
+    info!("Initializing Seasonal Compressor");
+    let mut c = Seasonal::new();
+    c.compress_bounded(data, DEFAULT_ERROR, None);
+    c.to_bytes()
+}
+
+/// Compresses the data with the detected period, within `max_error`
+pub fn seasonal_allowed_error(data: &[f64], max_error: f64) -> CompressorResult {
+    seasonal_with_period(data, max_error, None)
+}
+
+/// Compresses the data with a known period (e.g. a day worth of samples), within `max_error`. The period is detected in the data if the hint is missing or doesn't fit twice in the data.
+pub fn seasonal_with_period(
+    data: &[f64],
+    max_error: f64,
+    period: Option<usize>,
+) -> CompressorResult {
+    info!(
+        "Initializing Seasonal Compressor. Max error: {} Period: {:?}",
+        max_error, period
+    );
+    let mut c = Seasonal::new();
+    c.compress_bounded(data, max_error, period);
+    let decompressed = c.to_data(data.len()).unwrap();
+    CompressorResult::new(c.to_bytes(), error_mape_exact_zeros(data, &decompressed))
+}
+
+pub fn seasonal_to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    let c = Seasonal::decompress(compressed_data)?;
+    c.to_data(sample_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::{fft::fft_allowed_error, pla::pla_allowed_error};
+    use std::f64::consts::PI;
+
+    /// A day of 288 samples (every 5 minutes), busy during the day, over a slow growth and some noise
+    fn daily(days: usize) -> Vec<f64> {
+        (0..288 * days)
+            .map(|i| {
+                let phase = (i % 288) as f64 / 288.0;
+                1000.0
+                    + i as f64 * 0.05
+                    + (phase * 2.0 * PI).sin().max(0.0) * 400.0
+                    + ((i * 7919) % 11) as f64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_seasonal_daily() {
+        let vector1 = daily(7);
+        let result = seasonal_allowed_error(&vector1, 0.02);
+        let c = Seasonal::decompress(&result.compressed_data).unwrap();
+        assert_eq!(c.period, 288);
+        assert!(c.profile.len() < 288);
+        assert!(result.error <= 0.02);
+        assert!(result.compressed_data.len() < vector1.len() * 8 / 10);
+        let out = seasonal_to_data(vector1.len(), &result.compressed_data).unwrap();
+        assert_eq!(error_mape_exact_zeros(&vector1, &out), result.error);
+    }
+
+    #[test]
+    fn test_seasonal_business_hours() {
+        // Two weeks of a service busy from 9 to 17, a step the other compressors need many points for
+        let vector1: Vec<f64> = (0..288 * 14)
+            .map(|i| {
+                let busy = (9..17).contains(&((i % 288) / 12));
+                1000.0 + i as f64 * 0.02 + if busy { 400.0 } else { 0.0 } + ((i * 7919) % 11) as f64
+            })
+            .collect();
+        let result = seasonal_allowed_error(&vector1, 0.01);
+        assert!(result.error <= 0.01);
+        assert!(
+            result.compressed_data.len() < pla_allowed_error(&vector1, 0.01).compressed_data.len()
+        );
+        assert!(
+            result.compressed_data.len() < fft_allowed_error(&vector1, 0.01).compressed_data.len()
+        );
+    }
+
+    #[test]
+    fn test_seasonal_period_hint() {
+        let vector1 = daily(3);
+        let hinted = seasonal_with_period(&vector1, 0.02, Some(288));
+        assert_eq!(
+            Seasonal::decompress(&hinted.compressed_data)
+                .unwrap()
+                .period,
+            288
+        );
+        // A hint that doesn't fit twice in the frame is ignored
+        let ignored = seasonal_with_period(&vector1, 0.02, Some(vector1.len()));
+        assert!(ignored.error <= 0.02);
+        // No season at all, the trend alone with exact zeros kept
+        let vector2 = vec![0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let result = seasonal_allowed_error(&vector2, 0.01);
+        let out = seasonal_to_data(vector2.len(), &result.compressed_data).unwrap();
+        assert_eq!(out[..2], [0.0, 0.0]);
+        assert!(result.error <= 0.01);
+    }
+
+    #[test]
+    fn test_corrupted_seasonal() {
+        let vector1: Vec<f64> = (0..100).map(|i| (i % 10) as f64 + 1.0).collect();
+        let mut c = Seasonal::new();
+        c.compress_bounded(&vector1, 0.0, Some(10));
+        assert_eq!(c.to_data(100).unwrap(), vector1);
+        c.corrections.push(Correction {
+            gap: 1000,
+            value: 1.0,
+        });
+        assert!(c.to_data(100).is_err());
+        let mut c = Seasonal::new();
+        c.period = 2;
+        c.profile = vec![1.0, 2.0, 3.0];
+        assert!(c.to_data(10).is_err());
+        assert!(seasonal_to_data(10, &[SEASONAL_COMPRESSOR_ID]).is_err());
+    }
+}
@@ -0,0 +1,383 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use super::{BinConfig, CompressorResult};
+use crate::{
+    error::Error,
+    utils::{error::calculate_error, round_f64, DECIMAL_PRECISION},
+};
+use bincode::{Decode, Encode};
+use log::{debug, info};
+use std::f64::consts::SQRT_2;
+
+const WAVELET_COMPRESSOR_ID: u8 = 90;
+
+/// Wavelet used for the transform
+#[derive(Encode, Decode, PartialEq, Debug, Clone, Copy)]
+pub enum WaveletFamily {
+    /// Steps, keeps sharp edges in place
+    Haar,
+    /// Daubechies with 4 taps, smoother, better for slopes
+    Daubechies4,
+}
+
+impl WaveletFamily {
+    /// Low pass filter of the orthonormal transform, the high pass one is derived from it
+    fn low_pass(&self) -> Vec<f64> {
+        match self {
+            WaveletFamily::Haar => vec![1.0 / SQRT_2, 1.0 / SQRT_2],
+            WaveletFamily::Daubechies4 => {
+                let sqrt_3 = 3f64.sqrt();
+                let norm = 4.0 * SQRT_2;
+                vec![
+                    (1.0 + sqrt_3) / norm,
+                    (3.0 + sqrt_3) / norm,
+                    (3.0 - sqrt_3) / norm,
+                    (1.0 - sqrt_3) / norm,
+                ]
+            }
+        }
+    }
+}
+
+/// Wavelet compressor. Applies a discrete wavelet transform to a signal, picks the N biggest coefficients,
+/// discards the rest. Always LOSSY
+///
+/// Unlike the FFT, each wavelet coefficient only covers part of the frame, so a burst in a flat series takes a
+/// handful of coefficients around it instead of being spread over the whole frame. The frame is padded with its
+/// last value up to a power of 2, and the transform wraps around its edges.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Wavelet {
+    pub id: u8,
+    pub family: WaveletFamily,
+    /// Kept coefficients as (positions since the previous kept coefficient, value)
+    pub coefficients: Vec<(u64, f32)>,
+    pub error: Option<f64>,
+}
+
+// Implementing the Encode manually because we don't want to encode the Error field, less bytes used.
+impl Encode for Wavelet {
+    fn encode<__E: ::bincode::enc::Encoder>(
+        &self,
+        encoder: &mut __E,
+    ) -> Result<(), ::bincode::error::EncodeError> {
+        Encode::encode(&self.id, encoder)?;
+        Encode::encode(&self.family, encoder)?;
+        Encode::encode(&self.coefficients, encoder)?;
+        Ok(())
+    }
+}
+
+impl Decode for Wavelet {
+    fn decode<__D: ::bincode::de::Decoder>(
+        decoder: &mut __D,
+    ) -> Result<Self, ::bincode::error::DecodeError> {
+        Ok(Self {
+            id: Decode::decode(decoder)?,
+            family: Decode::decode(decoder)?,
+            coefficients: Decode::decode(decoder)?,
+            error: None,
+        })
+    }
+}
+
+bincode::impl_borrow_decode!(Wavelet);
+
+/// One level of the forward transform over `data`, approximation to the first half and details to the second
+fn forward_step(data: &mut [f64], low_pass: &[f64]) {
+    let len = data.len();
+    let taps = low_pass.len();
+    let mut out = vec![0.0; len];
+    for i in 0..len / 2 {
+        for (k, h) in low_pass.iter().enumerate() {
+            let x = data[(2 * i + k) % len];
+            // High pass is the low pass reversed, with alternating signs
+            let g = if k % 2 == 0 { 1.0 } else { -1.0 } * low_pass[taps - 1 - k];
+            out[i] += h * x;
+            out[len / 2 + i] += g * x;
+        }
+    }
+    data.copy_from_slice(&out);
+}
+
+/// One level of the inverse transform, the transform is orthonormal so it's the transpose of `forward_step`
+fn inverse_step(data: &mut [f64], low_pass: &[f64]) {
+    let len = data.len();
+    let taps = low_pass.len();
+    let mut out = vec![0.0; len];
+    for i in 0..len / 2 {
+        for (k, h) in low_pass.iter().enumerate() {
+            let g = if k % 2 == 0 { 1.0 } else { -1.0 } * low_pass[taps - 1 - k];
+            out[(2 * i + k) % len] += h * data[i] + g * data[len / 2 + i];
+        }
+    }
+    data.copy_from_slice(&out);
+}
+
+/// Lengths the transform works on, from the full padded frame down to the smallest level
+fn levels(len: usize, taps: usize) -> Vec<usize> {
+    let mut levels = Vec::new();
+    let mut level = len;
+    while level >= taps {
+        levels.push(level);
+        level /= 2;
+    }
+    levels
+}
+
+impl Wavelet {
+    pub fn new(family: WaveletFamily) -> Self {
+        debug!("Wavelet compressor: {:?}", family);
+        Wavelet {
+            id: WAVELET_COMPRESSOR_ID,
+            family,
+            coefficients: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Full transform of the data, padded with the last value up to a power of 2
+    fn transform(&self, data: &[f64]) -> Vec<f64> {
+        let mut buffer = data.to_vec();
+        if let Some(&last) = data.last() {
+            buffer.resize(data.len().next_power_of_two(), last);
+        }
+        let low_pass = self.family.low_pass();
+        for level in levels(buffer.len(), low_pass.len()) {
+            forward_step(&mut buffer[..level], &low_pass);
+        }
+        buffer
+    }
+
+    /// Inverse transform of the padded coefficients in `buffer`, cut down to `frame_size` values
+    fn inverse(&self, mut buffer: Vec<f64>, frame_size: usize) -> Vec<f64> {
+        let low_pass = self.family.low_pass();
+        for level in levels(buffer.len(), low_pass.len()).into_iter().rev() {
+            inverse_step(&mut buffer[..level], &low_pass);
+        }
+        buffer.truncate(frame_size);
+        buffer
+            .into_iter()
+            .map(|x| round_f64(x, DECIMAL_PRECISION))
+            .collect()
+    }
+
+    /// Error of keeping the `count` biggest coefficients, as stored, without going through `to_data`
+    fn kept_error(&self, data: &[f64], coefficients: &[f64], order: &[usize], count: usize) -> f64 {
+        let mut buffer = vec![0.0; coefficients.len()];
+        for &pos in &order[..count.min(order.len())] {
+            buffer[pos] = coefficients[pos] as f32 as f64;
+        }
+        calculate_error(data, &self.inverse(buffer, data.len()))
+    }
+
+    /// Keeps the `count` biggest coefficients, `order` are the positions of the coefficients from the biggest
+    fn keep(&mut self, coefficients: &[f64], order: &[usize], count: usize) {
+        let mut positions = order[..count.min(order.len())].to_vec();
+        positions.sort_unstable();
+        let mut previous = 0;
+        self.coefficients = positions
+            .into_iter()
+            .map(|pos| {
+                let gap = (pos - previous) as u64;
+                previous = pos;
+                (gap, coefficients[pos] as f32)
+            })
+            .collect();
+    }
+
+    /// Positions of the non zero coefficients, from the biggest to the smallest
+    fn order(coefficients: &[f64]) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..coefficients.len())
+            .filter(|&pos| coefficients[pos] != 0.0)
+            .collect();
+        order.sort_by(|&a, &b| coefficients[b].abs().total_cmp(&coefficients[a].abs()));
+        order
+    }
+
+    /// Compresses data via a wavelet transform
+    /// The set of coefficients to store is 1/100 of the data length OR 3, which is bigger.
+    pub fn compress(&mut self, data: &[f64]) {
+        let coefficients = self.transform(data);
+        let order = Wavelet::order(&coefficients);
+        let count = 3.max(data.len() / 100);
+        self.keep(&coefficients, &order, count);
+        self.error = Some(self.kept_error(data, &coefficients, &order, count));
+    }
+
+    /// Compresses data via a wavelet transform, keeping the fewest coefficients that are within `max_err`.
+    /// The error mostly goes down as coefficients are added, so their number is found by bisection.
+    pub fn compress_bounded(&mut self, data: &[f64], max_err: f64) {
+        let coefficients = self.transform(data);
+        let order = Wavelet::order(&coefficients);
+        let (mut low, mut high) = (1, order.len().max(1));
+        while low < high {
+            let count = (low + high) / 2;
+            if self.kept_error(data, &coefficients, &order, count) <= max_err {
+                high = count;
+            } else {
+                low = count + 1;
+            }
+        }
+        self.keep(&coefficients, &order, low);
+        let current_err = self.kept_error(data, &coefficients, &order, low);
+        self.error = Some(current_err);
+        debug!(
+            "Kept {} out of {} coefficients, Error: {}",
+            self.coefficients.len(),
+            coefficients.len(),
+            current_err
+        );
+    }
+
+    /// Receives a data stream and generates a Wavelet
+    pub fn decompress(data: &[u8]) -> Result<Self, Error> {
+        let config = BinConfig::get();
+        let (wavelet, _) = bincode::decode_from_slice(data, config)?;
+        Ok(wavelet)
+    }
+
+    /// This function transforms the structure in a Binary stream to be appended to the frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        bincode::encode_to_vec(self, config).unwrap()
+    }
+
+    /// Returns an array of data, runs the inverse transform over the kept coefficients
+    pub fn to_data(&self, frame_size: usize) -> Result<Vec<f64>, Error> {
+        let len = frame_size.next_power_of_two();
+        let mut buffer = vec![0.0; len];
+        let mut pos: u64 = 0;
+        for (i, &(gap, value)) in self.coefficients.iter().enumerate() {
+            pos = pos.saturating_add(gap);
+            if pos >= len as u64 || (i > 0 && gap == 0) {
+                return Err(Error::CorruptData(format!(
+                    "wavelet coefficient {} out of the {} of the frame",
+                    pos, len
+                )));
+            }
+            buffer[pos as usize] = value as f64;
+        }
+        Ok(self.inverse(buffer, frame_size))
+    }
+}
+
+/// Compresses a data segment with both wavelets. Both keep the same number of coefficients, the one with the
+/// smallest error is kept.
+pub fn wavelet(data: &[f64]) -> Vec<u8> {
+    info!("Initializing Wavelet Compressor");
+    [WaveletFamily::Haar, WaveletFamily::Daubechies4]
+        .into_iter()
+        .map(|family| {
+            let mut c = Wavelet::new(family);
+            c.compress(data);
+            c
+        })
+        .min_by(|a, b| a.error.unwrap().total_cmp(&b.error.unwrap()))
+        .unwrap()
+        .to_bytes()
+}
+
+/// Compress targeting a specific max error allowed, with both wavelets. The smallest result is kept.
+pub fn wavelet_compressor(data: &[f64], allowed_error: f64) -> CompressorResult {
+    info!(
+        "Initializing Wavelet Compressor. Max error: {}",
+        allowed_error
+    );
+    let mut results = Vec::new();
+    for family in [WaveletFamily::Haar, WaveletFamily::Daubechies4] {
+        let mut c = Wavelet::new(family);
+        c.compress_bounded(data, allowed_error);
+        results.push(CompressorResult::new(c.to_bytes(), c.error.unwrap_or(0.0)));
+    }
+    results
+        .into_iter()
+        .min_by_key(|r| (r.error > allowed_error, r.compressed_data.len()))
+        .unwrap()
+}
+
+pub fn wavelet_to_data(sample_number: usize, compressed_data: &[u8]) -> Result<Vec<f64>, Error> {
+    let c = Wavelet::decompress(compressed_data)?;
+    c.to_data(sample_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::fft::fft_allowed_error;
+
+    #[test]
+    fn test_wavelet_roundtrip() {
+        let vector1 = vec![1.0, 1.0, 1.0, 1.0, 2.0, 1.0, 1.0, 1.0, 3.0, 1.0, 1.0, 5.0];
+        for family in [WaveletFamily::Haar, WaveletFamily::Daubechies4] {
+            let mut c = Wavelet::new(family);
+            let coefficients = c.transform(&vector1);
+            assert_eq!(coefficients.len(), 16);
+            c.keep(&coefficients, &Wavelet::order(&coefficients), 16);
+            assert_eq!(
+                wavelet_to_data(vector1.len(), &c.to_bytes()).unwrap(),
+                vector1
+            );
+        }
+        // The approximation of a constant is its only coefficient
+        let constant = vec![7.0; 100];
+        let mut c = Wavelet::new(WaveletFamily::Haar);
+        c.compress(&constant);
+        assert_eq!(c.coefficients.len(), 1);
+        assert_eq!(wavelet_to_data(100, &c.to_bytes()).unwrap(), constant);
+    }
+
+    #[test]
+    fn test_wavelet_burst() {
+        // Flat latency with a burst, the FFT spreads the burst over the whole frame
+        let mut vector1 = vec![10.0; 2048];
+        for (i, v) in vector1.iter_mut().enumerate().skip(1000).take(20) {
+            *v = 200.0 + i as f64;
+        }
+        let result = wavelet_compressor(&vector1, 0.01);
+        let out = wavelet_to_data(vector1.len(), &result.compressed_data).unwrap();
+        assert!(calculate_error(&vector1, &out) <= 0.01);
+        assert_eq!(calculate_error(&vector1, &out), result.error);
+        let fft_result = fft_allowed_error(&vector1, 0.01);
+        assert!(result.compressed_data.len() * 4 < fft_result.compressed_data.len());
+    }
+
+    #[test]
+    fn test_wavelet_family() {
+        // Without an error bound the family with the smallest error is kept, like with one
+        let vector1: Vec<f64> = (0..1024)
+            .map(|i| (i as f64 / 50.0).sin() * 20.0 + 50.0)
+            .collect();
+        let errors: Vec<f64> = [WaveletFamily::Haar, WaveletFamily::Daubechies4]
+            .into_iter()
+            .map(|family| {
+                let mut c = Wavelet::new(family);
+                c.compress(&vector1);
+                c.error.unwrap()
+            })
+            .collect();
+        let out = wavelet_to_data(vector1.len(), &wavelet(&vector1)).unwrap();
+        assert_eq!(calculate_error(&vector1, &out), errors[0].min(errors[1]));
+    }
+
+    #[test]
+    fn test_corrupted_coefficients() {
+        let vector1: Vec<f64> = (0..100).map(|i| (i % 7) as f64).collect();
+        let compressed = wavelet(&vector1);
+        assert!(wavelet_to_data(2, &compressed).is_err());
+        assert!(wavelet_to_data(vector1.len(), &compressed[..compressed.len() - 1]).is_err());
+    }
+}
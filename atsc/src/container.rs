@@ -0,0 +1,374 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::compressor::BinConfig;
+use crate::data::CompressedStream;
+use crate::error::Error;
+use crate::utils::checksum::crc32;
+use bincode::{Decode, Encode};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Every container starts with this
+pub const CONTAINER_MAGIC: [u8; 4] = *b"BRRS";
+/// Version of the container layout written by this release
+pub const CONTAINER_VERSION: u16 = 1;
+/// Marks the end of a container, after the directory
+pub const DIRECTORY_MAGIC: [u8; 4] = *b"BRSD";
+/// The trailer is the directory checksum (4 bytes), the directory size (4 bytes) and the magic (4 bytes)
+pub const DIRECTORY_TRAILER_SIZE: usize = 12;
+/// Magic (4 bytes) and version (1 byte while it stays under 251)
+const CONTAINER_HEADER_SIZE: usize = 5;
+
+/// Location and summary of a series in a serialized container
+#[derive(Encode, Decode, Debug, Clone, PartialEq)]
+pub struct SeriesEntry {
+    pub name: String,
+    /// Byte offset of the series stream from the start of the container
+    pub offset: u64,
+    /// Bytes used by the series stream
+    pub size: u64,
+    pub frame_count: u64,
+    pub sample_count: u64,
+}
+
+/// Many named series in a single file.
+///
+/// Layout, as written by `to_bytes`:
+/// - The magic and the container version
+/// - Each series, as a full `CompressedStream` with its own checksums and frame index
+/// - The directory, listing where each series is
+/// - The trailer: the CRC-32 of the directory (4 bytes, little endian), its size (4 bytes, little endian) and
+///   the directory magic
+///
+/// The directory is at the end, so a single series can be read by seeking straight to it.
+#[derive(Debug, Clone, Default)]
+pub struct SeriesContainer {
+    /// Directory entries and the serialized stream of each series, offsets are set when the container is written
+    series: Vec<(SeriesEntry, Vec<u8>)>,
+}
+
+impl SeriesContainer {
+    pub fn new() -> Self {
+        SeriesContainer { series: Vec::new() }
+    }
+
+    /// Adds a series to the container, fails if there is already a series with that name
+    pub fn add(&mut self, name: &str, stream: CompressedStream) -> Result<(), Error> {
+        if self.entry(name).is_some() {
+            return Err(Error::DuplicateSeries(name.to_string()));
+        }
+        let entry = SeriesEntry {
+            name: name.to_string(),
+            offset: 0,
+            size: 0,
+            frame_count: stream.header().frame_count() as u64,
+            sample_count: stream.sample_count() as u64,
+        };
+        let bytes = stream.to_bytes();
+        self.series.push((
+            SeriesEntry {
+                size: bytes.len() as u64,
+                ..entry
+            },
+            bytes,
+        ));
+        Ok(())
+    }
+
+    /// Removes a series from the container, returning it
+    pub fn remove(&mut self, name: &str) -> Result<CompressedStream, Error> {
+        let position = self
+            .series
+            .iter()
+            .position(|(entry, _)| entry.name == name)
+            .ok_or_else(|| Error::SeriesNotFound(name.to_string()))?;
+        let (_, bytes) = self.series.remove(position);
+        CompressedStream::from_bytes(&bytes)
+    }
+
+    /// Gets a series by name
+    pub fn get(&self, name: &str) -> Result<CompressedStream, Error> {
+        let (_, bytes) = self
+            .series
+            .iter()
+            .find(|(entry, _)| entry.name == name)
+            .ok_or_else(|| Error::SeriesNotFound(name.to_string()))?;
+        CompressedStream::from_bytes(bytes)
+    }
+
+    fn entry(&self, name: &str) -> Option<&SeriesEntry> {
+        self.series
+            .iter()
+            .map(|(entry, _)| entry)
+            .find(|entry| entry.name == name)
+    }
+
+    /// Directory of the container, in the order the series were added
+    pub fn entries(&self) -> Vec<SeriesEntry> {
+        self.series.iter().map(|(entry, _)| entry.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.series.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.series.is_empty()
+    }
+
+    /// Transforms the container into bytes to be written to a file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        let mut out = bincode::encode_to_vec((CONTAINER_MAGIC, CONTAINER_VERSION), config).unwrap();
+        let mut directory = Vec::with_capacity(self.series.len());
+        for (entry, bytes) in self.series.iter() {
+            directory.push(SeriesEntry {
+                offset: out.len() as u64,
+                ..entry.clone()
+            });
+            out.extend(bytes);
+        }
+        let directory_bytes = bincode::encode_to_vec(&directory, config).unwrap();
+        out.extend(&directory_bytes);
+        out.extend(crc32(&directory_bytes).to_le_bytes());
+        out.extend((directory_bytes.len() as u32).to_le_bytes());
+        out.extend(DIRECTORY_MAGIC);
+        out
+    }
+
+    /// Reads a container from its bytes. The series are only checked when they are read.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let directory = read_directory_from(data)?;
+        let mut series = Vec::with_capacity(directory.len());
+        for entry in directory {
+            let bytes = data[entry.offset as usize..(entry.offset + entry.size) as usize].to_vec();
+            series.push((entry, bytes));
+        }
+        Ok(SeriesContainer { series })
+    }
+
+    /// Checks if the bytes start like a container
+    pub fn is_container(data: &[u8]) -> bool {
+        data.starts_with(&CONTAINER_MAGIC)
+    }
+
+    /// Reads the directory of a container from a reader, without reading any series
+    pub fn read_directory<R: Read + Seek>(reader: &mut R) -> Result<Vec<SeriesEntry>, Error> {
+        let container_len = reader.seek(SeekFrom::End(0))?;
+        let mut header = [0u8; CONTAINER_HEADER_SIZE];
+        reader.seek(SeekFrom::Start(0))?;
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| Error::TruncatedInput)?;
+        check_header(&header)?;
+        if container_len < (CONTAINER_HEADER_SIZE + DIRECTORY_TRAILER_SIZE) as u64 {
+            return Err(Error::TruncatedInput);
+        }
+        let mut trailer = [0u8; DIRECTORY_TRAILER_SIZE];
+        reader.seek(SeekFrom::End(-(DIRECTORY_TRAILER_SIZE as i64)))?;
+        reader.read_exact(&mut trailer)?;
+        let (checksum, size) = read_trailer(&trailer)?;
+        let directory_end = container_len - DIRECTORY_TRAILER_SIZE as u64;
+        if size as u64 > directory_end - CONTAINER_HEADER_SIZE as u64 {
+            return Err(Error::TruncatedInput);
+        }
+        let mut directory_bytes = vec![0u8; size];
+        reader.seek(SeekFrom::Start(directory_end - size as u64))?;
+        reader.read_exact(&mut directory_bytes)?;
+        decode_directory(&directory_bytes, checksum, directory_end - size as u64)
+    }
+
+    /// Reads a single series from a reader, only the directory and that series are read
+    pub fn read_series<R: Read + Seek>(
+        reader: &mut R,
+        name: &str,
+    ) -> Result<CompressedStream, Error> {
+        let directory = SeriesContainer::read_directory(reader)?;
+        let entry = directory
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| Error::SeriesNotFound(name.to_string()))?;
+        let mut bytes = vec![0u8; entry.size as usize];
+        reader.seek(SeekFrom::Start(entry.offset))?;
+        reader.read_exact(&mut bytes)?;
+        CompressedStream::from_bytes(&bytes)
+    }
+}
+
+fn check_header(header: &[u8]) -> Result<(), Error> {
+    if !SeriesContainer::is_container(header) {
+        return Err(Error::CorruptData(
+            "not a series container, magic bytes don't match".to_string(),
+        ));
+    }
+    let config = BinConfig::get();
+    let (version, _): (u16, usize) = bincode::decode_from_slice(&header[4..], config)?;
+    if version > CONTAINER_VERSION {
+        return Err(Error::UnsupportedFormat(format!(
+            "container version {} is newer than the supported version {}, please upgrade atsc",
+            version, CONTAINER_VERSION
+        )));
+    }
+    Ok(())
+}
+
+/// Reads the trailer at the end of a container, returning the checksum and size of the directory before it
+fn read_trailer(trailer: &[u8]) -> Result<(u32, usize), Error> {
+    if trailer[8..] != DIRECTORY_MAGIC {
+        return Err(Error::CorruptData(
+            "container directory not found".to_string(),
+        ));
+    }
+    let checksum = u32::from_le_bytes(trailer[0..4].try_into().unwrap());
+    let size = u32::from_le_bytes(trailer[4..8].try_into().unwrap());
+    Ok((checksum, size as usize))
+}
+
+/// Decodes the directory, checking that every series is inside the `series_end` bytes before it
+fn decode_directory(
+    data: &[u8],
+    checksum: u32,
+    series_end: u64,
+) -> Result<Vec<SeriesEntry>, Error> {
+    if crc32(data) != checksum {
+        return Err(Error::CorruptData(
+            "container directory checksum mismatch".to_string(),
+        ));
+    }
+    let config = BinConfig::get();
+    let (directory, _): (Vec<SeriesEntry>, usize) = bincode::decode_from_slice(data, config)?;
+    for entry in directory.iter() {
+        let end = entry.offset.checked_add(entry.size);
+        if entry.offset < CONTAINER_HEADER_SIZE as u64 || end.map_or(true, |end| end > series_end) {
+            return Err(Error::CorruptData(format!(
+                "series {} is outside of the container",
+                entry.name
+            )));
+        }
+    }
+    Ok(directory)
+}
+
+/// Same as `SeriesContainer::read_directory`, on a container already in memory
+fn read_directory_from(data: &[u8]) -> Result<Vec<SeriesEntry>, Error> {
+    if data.len() < CONTAINER_HEADER_SIZE + DIRECTORY_TRAILER_SIZE {
+        return Err(Error::TruncatedInput);
+    }
+    check_header(&data[..CONTAINER_HEADER_SIZE])?;
+    let directory_end = data.len() - DIRECTORY_TRAILER_SIZE;
+    let (checksum, size) = read_trailer(&data[directory_end..])?;
+    if size > directory_end - CONTAINER_HEADER_SIZE {
+        return Err(Error::TruncatedInput);
+    }
+    let series_end = directory_end - size;
+    decode_directory(
+        &data[series_end..directory_end],
+        checksum,
+        series_end as u64,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::Compressor;
+    use std::io::Cursor;
+
+    fn stream(values: &[f64]) -> CompressedStream {
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_with(values, Compressor::Noop).unwrap();
+        cs
+    }
+
+    fn container() -> SeriesContainer {
+        let mut container = SeriesContainer::new();
+        container.add("cpu", stream(&[1.0, 2.0, 3.0])).unwrap();
+        container.add("memory", stream(&[4.0, 5.0])).unwrap();
+        container
+    }
+
+    #[test]
+    fn test_container_roundtrip() {
+        let b = container().to_bytes();
+        assert!(SeriesContainer::is_container(&b));
+        let container = SeriesContainer::from_bytes(&b).unwrap();
+        assert_eq!(container.len(), 2);
+        let entries = container.entries();
+        assert_eq!(entries[0].name, "cpu");
+        assert_eq!(entries[0].sample_count, 3);
+        assert_eq!(entries[1].frame_count, 1);
+        assert_eq!(
+            container.get("memory").unwrap().decompress().unwrap(),
+            [4.0, 5.0]
+        );
+        assert!(matches!(
+            container.get("disk"),
+            Err(Error::SeriesNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_legacy_series() {
+        // Stream written before the header was versioned, its header has no frame count
+        let b = [66, 82, 82, 79, 0, 1, 41, 251, 0, 4, 3, 3, 30, 3, 1];
+        let mut container = SeriesContainer::new();
+        container
+            .add("cpu", CompressedStream::from_bytes(&b).unwrap())
+            .unwrap();
+        let container = SeriesContainer::from_bytes(&container.to_bytes()).unwrap();
+        assert_eq!(container.entries()[0].frame_count, 1);
+        assert_eq!(container.entries()[0].sample_count, 1024);
+        assert_eq!(
+            container.get("cpu").unwrap().decompress().unwrap(),
+            vec![1.0; 1024]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_series() {
+        let mut container = container();
+        assert!(matches!(
+            container.add("cpu", stream(&[1.0])),
+            Err(Error::DuplicateSeries(_))
+        ));
+        container.remove("cpu").unwrap();
+        container.add("cpu", stream(&[1.0])).unwrap();
+        assert_eq!(container.entries()[1].name, "cpu");
+    }
+
+    #[test]
+    fn test_read_series() {
+        let mut b = container().to_bytes();
+        let directory = SeriesContainer::read_directory(&mut Cursor::new(&b)).unwrap();
+        assert_eq!(directory.len(), 2);
+        // Breaking the first series doesn't prevent reading the second one
+        b[directory[0].offset as usize + 8] ^= 0x01;
+        let mut reader = Cursor::new(&b);
+        assert!(SeriesContainer::read_series(&mut reader, "cpu").is_err());
+        let memory = SeriesContainer::read_series(&mut reader, "memory").unwrap();
+        assert_eq!(memory.decompress().unwrap(), [4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_damaged_directory() {
+        let mut b = container().to_bytes();
+        let pos = b.len() - DIRECTORY_TRAILER_SIZE - 1;
+        b[pos] ^= 0x01;
+        assert!(SeriesContainer::from_bytes(&b).is_err());
+        assert!(SeriesContainer::read_directory(&mut Cursor::new(&b)).is_err());
+        assert!(SeriesContainer::from_bytes(&b[..10]).is_err());
+    }
+}
@@ -15,39 +15,205 @@ limitations under the License.
 */
 
 use crate::compressor::{BinConfig, Compressor};
-use crate::frame::CompressorFrame;
-use crate::header::CompressorHeader;
-use bincode::{Decode, Encode};
-use log::debug;
+use crate::error::Error;
+use crate::frame::{CompressorFrame, FrameSummary};
+use crate::header::{
+    CompressorHeader, FEATURE_CHECKSUMS, FEATURE_EXCEPTIONS, FEATURE_INDEX, FEATURE_LAPPED,
+    FEATURE_METADATA, FEATURE_RESIDUALS, FORMAT_VERSION,
+};
+use crate::index::{FrameIndex, FrameIndexEntry, TRAILER_SIZE};
+use crate::metadata::Metadata;
+use crate::utils::checksum::{crc32, Crc32};
+use bincode::Decode;
+use log::{debug, warn};
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
 
-#[derive(Encode, Decode, Debug, Clone)]
+/// A stream of compressed frames.
+///
+/// Layout, as written by `to_bytes`:
+/// - The header, holding the CRC-32 of the whole stream, calculated over the metadata checksum and the frames
+///   checksums
+/// - The metadata block, if any: its length and bytes, followed by its CRC-32 (4 bytes, little endian)
+/// - For each frame: its length and bytes, followed by its CRC-32 (4 bytes, little endian)
+/// - The frame index and its trailer, see `FrameIndex`
+///
+/// The header is the only source of truth of how many frames are in the stream, anything after those frames can be
+/// ignored. This is what makes `append_to_file` safe to interrupt.
+#[derive(Debug, Clone)]
 pub struct CompressedStream {
     header: CompressorHeader,
+    metadata: Option<Metadata>,
     data_frames: Vec<CompressorFrame>,
 }
 
+/// Result of checking a serialized stream against its checksums
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamVerification {
+    /// Number of frames announced by the header
+    pub frame_count: usize,
+    /// Position of the frames that don't match their checksum or that couldn't be read at all
+    pub corrupt_frames: Vec<usize>,
+    /// If the checksum of the whole stream matches
+    pub stream_checksum_ok: bool,
+    /// If the metadata block matches its checksum, true for streams without metadata
+    pub metadata_ok: bool,
+}
+
+impl StreamVerification {
+    pub fn is_ok(&self) -> bool {
+        self.corrupt_frames.is_empty() && self.stream_checksum_ok && self.metadata_ok
+    }
+}
+
+/// Decodes a value from `data` at `offset`, moving the offset past it
+fn decode_at<T: Decode>(data: &[u8], offset: &mut usize) -> Result<T, Error> {
+    let config = BinConfig::get();
+    let (value, read) = bincode::decode_from_slice(&data[*offset..], config)?;
+    *offset += read;
+    Ok(value)
+}
+
+/// Reads a little endian CRC-32 from `data` at `offset`, moving the offset past it
+fn read_checksum(data: &[u8], offset: &mut usize) -> Result<u32, Error> {
+    let bytes = data
+        .get(*offset..*offset + 4)
+        .ok_or(Error::TruncatedInput)?;
+    *offset += 4;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Appends to `out` the samples of a decompressed frame that fall in [start, end).
+/// `frame_start` is the position of the first sample of the frame in the stream.
+fn extend_with_range(
+    out: &mut Vec<f64>,
+    samples: &[f64],
+    frame_start: usize,
+    start: usize,
+    end: usize,
+) {
+    let from = start.saturating_sub(frame_start).min(samples.len());
+    let to = end.saturating_sub(frame_start).min(samples.len());
+    out.extend_from_slice(&samples[from..to]);
+}
+
+/// Generates a frame from its bytes, in the layout the header says the frames of the stream are in
+fn decode_frame(
+    header: &CompressorHeader,
+    frame_bytes: &[u8],
+    checksum: u32,
+) -> Result<CompressorFrame, Error> {
+    if header.has_features(FEATURE_LAPPED) {
+        CompressorFrame::from_lapped_bytes(frame_bytes, checksum)
+    } else {
+        CompressorFrame::from_bytes(frame_bytes, checksum)
+    }
+}
+
+/// Reads the frame at `entry` of the frame index
+fn read_frame<R: Read + Seek>(
+    reader: &mut R,
+    header: &CompressorHeader,
+    entry: &FrameIndexEntry,
+) -> Result<CompressorFrame, Error> {
+    let mut frame_data = vec![0u8; entry.size as usize];
+    reader.seek(SeekFrom::Start(entry.offset))?;
+    reader.read_exact(&mut frame_data)?;
+    let (frame_bytes, checksum) = read_block(&frame_data, &mut 0)?;
+    decode_frame(header, frame_bytes, checksum)
+}
+
+/// Reads the frame index from the end of a stream. Returns None if there's no usable index, a damaged index is not
+/// an error since the stream can still be read without it.
+fn read_index<R: Read + Seek>(
+    reader: &mut R,
+    header: &CompressorHeader,
+) -> Result<Option<FrameIndex>, Error> {
+    let stream_len = reader.seek(SeekFrom::End(0))?;
+    if stream_len < TRAILER_SIZE as u64 {
+        return Ok(None);
+    }
+    let mut trailer = [0u8; TRAILER_SIZE];
+    reader.seek(SeekFrom::Start(stream_len - TRAILER_SIZE as u64))?;
+    reader.read_exact(&mut trailer)?;
+    let Some((checksum, size)) = FrameIndex::read_trailer(&trailer) else {
+        return Ok(None);
+    };
+    if size as u64 > stream_len - TRAILER_SIZE as u64 {
+        return Ok(None);
+    }
+    let mut index_bytes = vec![0u8; size];
+    reader.seek(SeekFrom::Start(
+        stream_len - TRAILER_SIZE as u64 - size as u64,
+    ))?;
+    reader.read_exact(&mut index_bytes)?;
+    match FrameIndex::from_bytes(&index_bytes, checksum) {
+        Ok(index) if index.entries().len() == header.frame_count() => Ok(Some(index)),
+        _ => {
+            warn!("Frame index is damaged, ignoring it");
+            Ok(None)
+        }
+    }
+}
+
+/// Every frame takes at least its length (1 byte) and its checksum (4 bytes)
+const MIN_FRAME_SIZE: usize = 5;
+
+/// Checks the frame count of the header against the bytes left in the stream, so a corrupted count is caught
+/// before anything is allocated for it
+fn check_frame_count(header: &CompressorHeader, available: usize) -> Result<(), Error> {
+    if header.frame_count() > available / MIN_FRAME_SIZE {
+        return Err(Error::TruncatedInput);
+    }
+    Ok(())
+}
+
+/// Reads a block (a frame or the metadata) and its checksum from `data` at `offset`, moving the offset past them.
+/// The length is checked against the available data, so a corrupted length can't trigger a huge allocation.
+fn read_block<'a>(data: &'a [u8], offset: &mut usize) -> Result<(&'a [u8], u32), Error> {
+    let len: u64 = decode_at(data, offset)?;
+    let available = data.len() - *offset;
+    if len > available as u64 {
+        return Err(Error::TruncatedInput);
+    }
+    let frame_bytes = &data[*offset..*offset + len as usize];
+    *offset += len as usize;
+    let checksum = read_checksum(data, offset)?;
+    Ok((frame_bytes, checksum))
+}
+
 impl CompressedStream {
     pub fn new() -> Self {
         CompressedStream {
             header: CompressorHeader::new(),
+            metadata: None,
             data_frames: Vec::new(),
         }
     }
 
     /// Compress a chunk of data adding it as a new frame to the current stream
-    pub fn compress_chunk(&mut self, chunk: &[f64]) {
+    pub fn compress_chunk(&mut self, chunk: &[f64]) -> Result<(), Error> {
         let mut compressor_frame = CompressorFrame::new(None);
-        compressor_frame.compress(chunk);
+        compressor_frame.compress(chunk)?;
         compressor_frame.close();
+        self.header.add_frame();
         self.data_frames.push(compressor_frame);
+        Ok(())
     }
 
     /// Compress a chunk of data with a specific compressor adding it as a new frame to the current stream
-    pub fn compress_chunk_with(&mut self, chunk: &[f64], compressor: Compressor) {
+    pub fn compress_chunk_with(
+        &mut self,
+        chunk: &[f64],
+        compressor: Compressor,
+    ) -> Result<(), Error> {
         let mut compressor_frame = CompressorFrame::new(Some(compressor));
-        compressor_frame.compress(chunk);
+        compressor_frame.compress(chunk)?;
         compressor_frame.close();
+        self.header.add_frame();
         self.data_frames.push(compressor_frame);
+        Ok(())
     }
 
     /// Compress a chunk of data with a specific compressor adding it as a new frame to the current stream
@@ -57,7 +223,7 @@ impl CompressedStream {
         compressor: Compressor,
         max_error: f32,
         compression_speed: usize,
-    ) {
+    ) -> Result<(), Error> {
         debug!(
             "Compressing chunk bounded with a max error of {}",
             max_error
@@ -65,44 +231,486 @@ impl CompressedStream {
         let mut compressor_frame = CompressorFrame::new(Some(compressor));
         match compressor {
             // Auto means the frame will pick the best
-            Compressor::Auto => compressor_frame.compress_best(chunk, max_error, compression_speed),
-            _ => compressor_frame.compress_bounded(chunk, max_error),
+            Compressor::Auto => {
+                compressor_frame.compress_best(chunk, max_error, compression_speed)?
+            }
+            _ => compressor_frame.compress_bounded(chunk, max_error)?,
         }
         compressor_frame.close();
+        self.header.add_frame();
         self.data_frames.push(compressor_frame);
+        Ok(())
     }
 
-    /// Transforms the whole CompressedStream into bytes to be written to a file
-    pub fn to_bytes(self) -> Vec<u8> {
-        // Will this chain encode??
+    /// Compress a chunk of data like `compress_chunk_bounded_with`, where the last `tail` samples of the chunk are the
+    /// first ones of the next chunk. They are compressed with this frame too, and crossfaded with the next frame on
+    /// decompression, so there is no step where the frames meet.
+    pub fn compress_chunk_lapped(
+        &mut self,
+        chunk: &[f64],
+        tail: usize,
+        compressor: Compressor,
+        max_error: f32,
+        compression_speed: usize,
+    ) -> Result<(), Error> {
+        debug!("Compressing chunk with a tail of {} samples", tail);
+        let mut compressor_frame = CompressorFrame::new(Some(compressor));
+        if tail > 0 {
+            compressor_frame.set_tail(tail.min(chunk.len().saturating_sub(1)));
+        }
+        match compressor {
+            Compressor::Auto => {
+                compressor_frame.compress_best(chunk, max_error, compression_speed)?
+            }
+            _ => compressor_frame.compress_bounded(chunk, max_error)?,
+        }
+        compressor_frame.close();
+        self.header.add_frame();
+        self.data_frames.push(compressor_frame);
+        Ok(())
+    }
+
+    /// Compress a chunk of data with a model compressor adding it as a new frame to the current stream, what the
+    /// model misses is kept as residuals so every sample is within `quantum / 2` of the original one
+    pub fn compress_chunk_with_residuals(
+        &mut self,
+        chunk: &[f64],
+        compressor: Compressor,
+        quantum: f64,
+    ) -> Result<(), Error> {
+        let mut compressor_frame = CompressorFrame::new(Some(compressor));
+        compressor_frame.compress_with_residuals(chunk, quantum)?;
+        compressor_frame.close();
+        self.header.add_frame();
+        self.data_frames.push(compressor_frame);
+        Ok(())
+    }
+
+    /// Transforms the whole CompressedStream into bytes to be written to a file.
+    /// The stream is always written in the current layout, even if it was read from an older one.
+    pub fn to_bytes(mut self) -> Vec<u8> {
         let config = BinConfig::get();
-        bincode::encode_to_vec(self, config).unwrap()
+        self.header.upgrade(self.data_frames.len());
+        self.header
+            .set_feature(FEATURE_METADATA, self.metadata.is_some());
+        self.header.set_feature(
+            FEATURE_EXCEPTIONS,
+            self.data_frames.iter().any(|f| f.has_exceptions()),
+        );
+        self.header.set_feature(
+            FEATURE_RESIDUALS,
+            self.data_frames.iter().any(|f| f.has_residuals()),
+        );
+        // Frames of a lapped stream all share its layout
+        let lapped = self.data_frames.iter().any(|f| f.is_lapped());
+        if lapped {
+            self.data_frames.iter_mut().for_each(|f| f.set_lapped());
+        }
+        self.header.set_feature(FEATURE_LAPPED, lapped);
+        self.header.set_stream_checksum(self.checksum());
+        let mut out = bincode::encode_to_vec(&self.header, config).unwrap();
+        if let Some(metadata) = &self.metadata {
+            let metadata_bytes = metadata.to_bytes();
+            out.extend(bincode::encode_to_vec(&metadata_bytes, config).unwrap());
+            out.extend(crc32(&metadata_bytes).to_le_bytes());
+        }
+        let mut index = FrameIndex::new();
+        for frame in self.data_frames.iter() {
+            let offset = out.len();
+            out.extend(bincode::encode_to_vec(frame.to_bytes(), config).unwrap());
+            out.extend(frame.checksum().to_le_bytes());
+            index.push(
+                offset as u64,
+                (out.len() - offset) as u64,
+                frame.sample_count() as u64,
+            );
+        }
+        out.extend(index.to_bytes());
+        out
     }
 
     /// Gets a binary stream and generates a Compressed Stream, at this point, anything inside the stream is
-    /// still in the compressed state
-    pub fn from_bytes(data: &[u8]) -> Self {
+    /// still in the compressed state.
+    /// Fails if the stream was written with a newer, incompatible, format version or if any checksum doesn't match.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let mut offset = 0;
+        let mut header: CompressorHeader = decode_at(data, &mut offset)?;
+        header.check()?;
+        if !header.has_features(FEATURE_CHECKSUMS) {
+            // Older layout, frames are a plain vector without checksums
+            let mut data_frames: Vec<CompressorFrame> = decode_at(data, &mut offset)?;
+            // Calculate the checksums, so the frames can be written in the current layout
+            data_frames.iter_mut().for_each(|f| f.close());
+            // The legacy header never counted its frames
+            header.set_frame_count(data_frames.len());
+            return Ok(CompressedStream {
+                header,
+                metadata: None,
+                data_frames,
+            });
+        }
+        let mut stream_crc = Crc32::new();
+        let mut metadata = None;
+        if header.has_features(FEATURE_METADATA) {
+            let (metadata_bytes, checksum) = read_block(data, &mut offset)?;
+            metadata = Some(Metadata::from_bytes(metadata_bytes, checksum)?);
+            stream_crc.update(&checksum.to_le_bytes());
+        }
+        check_frame_count(&header, data.len() - offset)?;
+        let mut data_frames = Vec::with_capacity(header.frame_count());
+        for i in 0..header.frame_count() {
+            let (frame_bytes, checksum) = read_block(data, &mut offset)?;
+            let frame = decode_frame(&header, frame_bytes, checksum)
+                .map_err(|e| Error::CorruptData(format!("frame {} is corrupted: {}", i, e)))?;
+            stream_crc.update(&checksum.to_le_bytes());
+            data_frames.push(frame);
+        }
+        if header.stream_checksum() != stream_crc.finalize() {
+            return Err(Error::CorruptData("stream checksum mismatch".to_string()));
+        }
+        Ok(CompressedStream {
+            header,
+            metadata,
+            data_frames,
+        })
+    }
+
+    /// Checks a binary stream against the checksums stored in it, without decompressing any frame.
+    /// Fails if the header can't be read or if the stream was written without checksums.
+    pub fn verify(data: &[u8]) -> Result<StreamVerification, Error> {
+        let mut offset = 0;
+        let header: CompressorHeader = decode_at(data, &mut offset)?;
+        header.check()?;
+        if !header.has_features(FEATURE_CHECKSUMS) {
+            return Err(Error::UnsupportedFormat(
+                "stream was written without checksums".to_string(),
+            ));
+        }
+        check_frame_count(&header, data.len() - offset)?;
+        let frame_count = header.frame_count();
+        let mut stream_crc = Crc32::new();
+        let mut corrupt_frames = Vec::new();
+        let mut metadata_ok = true;
+        if header.has_features(FEATURE_METADATA) {
+            match read_block(data, &mut offset) {
+                Ok((metadata_bytes, checksum)) => {
+                    metadata_ok = crc32(metadata_bytes) == checksum;
+                    stream_crc.update(&checksum.to_le_bytes());
+                }
+                Err(_) => {
+                    return Ok(StreamVerification {
+                        frame_count,
+                        corrupt_frames: (0..frame_count).collect(),
+                        stream_checksum_ok: false,
+                        metadata_ok: false,
+                    });
+                }
+            }
+        }
+        for i in 0..frame_count {
+            match read_block(data, &mut offset) {
+                Ok((frame_bytes, checksum)) => {
+                    if crc32(frame_bytes) != checksum {
+                        corrupt_frames.push(i);
+                    }
+                    stream_crc.update(&checksum.to_le_bytes());
+                }
+                Err(_) => {
+                    // Can't find where the next frames are, consider all of them lost
+                    corrupt_frames.extend(i..frame_count);
+                    return Ok(StreamVerification {
+                        frame_count,
+                        corrupt_frames,
+                        stream_checksum_ok: false,
+                        metadata_ok,
+                    });
+                }
+            }
+        }
+        let stream_checksum_ok = header.stream_checksum() == stream_crc.finalize();
+        Ok(StreamVerification {
+            frame_count,
+            corrupt_frames,
+            stream_checksum_ok,
+            metadata_ok,
+        })
+    }
+
+    /// CRC-32 of the whole stream, calculated over the checksum of the metadata and the checksum of each frame
+    fn checksum(&self) -> u32 {
+        let mut crc = Crc32::new();
+        if let Some(metadata) = &self.metadata {
+            crc.update(&crc32(&metadata.to_bytes()).to_le_bytes());
+        }
+        for frame in self.data_frames.iter() {
+            crc.update(&frame.checksum().to_le_bytes());
+        }
+        crc.finalize()
+    }
+
+    /// Returns the header of the stream
+    pub fn header(&self) -> &CompressorHeader {
+        &self.header
+    }
+
+    /// Summary of the samples of each frame, None for frames written without one. Nothing is decompressed.
+    pub fn frame_summaries(&self) -> Vec<Option<FrameSummary>> {
+        self.data_frames.iter().map(|f| f.summary()).collect()
+    }
+
+    /// Summary of all the samples of the stream, from the summaries of its frames. Nothing is decompressed.
+    /// None if the stream is empty or if any frame was written without a summary.
+    pub fn summary(&self) -> Option<FrameSummary> {
+        let mut summaries = self.data_frames.iter().map(|f| f.summary());
+        let first = summaries.next()??;
+        summaries.try_fold(first, |summary, next| Some(summary.merge(&next?)))
+    }
+
+    /// Number of samples in the stream
+    pub fn sample_count(&self) -> usize {
+        self.data_frames.iter().map(|f| f.sample_count()).sum()
+    }
+
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Sets the metadata written along with the stream, replacing any previous one
+    pub fn set_metadata(&mut self, metadata: Metadata) {
+        self.metadata = Some(metadata);
+    }
+
+    /// Reads the metadata of a stream from a reader, only the header and the metadata block are read.
+    /// Returns None if the stream has no metadata.
+    pub fn read_metadata<R: Read>(reader: &mut R) -> Result<Option<Metadata>, Error> {
+        let config = BinConfig::get();
+        let header: CompressorHeader = bincode::decode_from_std_read(reader, config)?;
+        header.check()?;
+        if !header.has_features(FEATURE_METADATA) {
+            return Ok(None);
+        }
+        let len: u64 = bincode::decode_from_std_read(reader, config)?;
+        // Read through `take`, so a corrupted length can't trigger a huge allocation
+        let mut metadata_bytes = Vec::new();
+        reader.take(len).read_to_end(&mut metadata_bytes)?;
+        if metadata_bytes.len() as u64 != len {
+            return Err(Error::TruncatedInput);
+        }
+        let mut checksum = [0u8; 4];
+        reader
+            .read_exact(&mut checksum)
+            .map_err(|_| Error::TruncatedInput)?;
+        Metadata::from_bytes(&metadata_bytes, u32::from_le_bytes(checksum)).map(Some)
+    }
+
+    /// Decompresses a stream from a reader, reading and decompressing only the frames holding the `len` samples
+    /// starting at `start`. Streams without a frame index are read in full.
+    pub fn read_range<R: Read + Seek>(
+        reader: &mut R,
+        start: usize,
+        len: usize,
+    ) -> Result<Vec<f64>, Error> {
+        let config = BinConfig::get();
+        reader.seek(SeekFrom::Start(0))?;
+        let header: CompressorHeader = bincode::decode_from_std_read(reader, config)?;
+        header.check()?;
+        let index = if header.has_features(FEATURE_INDEX) {
+            read_index(reader, &header)?
+        } else {
+            None
+        };
+        let Some(index) = index else {
+            debug!("No frame index available, reading the whole stream");
+            let mut data = Vec::new();
+            reader.seek(SeekFrom::Start(0))?;
+            reader.read_to_end(&mut data)?;
+            return CompressedStream::from_bytes(&data)?.decompress_range(start, len);
+        };
+        let end = start.saturating_add(len);
+        let mut out = Vec::new();
+        let entries = index.entries();
+        let mut previous_tail = Vec::new();
+        for (i, entry) in entries.iter().enumerate() {
+            if !entry.overlaps(start as u64, end as u64) {
+                continue;
+            }
+            if previous_tail.is_empty() && i > 0 && header.has_features(FEATURE_LAPPED) {
+                // The first samples of the frame are crossfaded with the tail of the frame before it
+                let previous = read_frame(reader, &header, &entries[i - 1])?;
+                previous_tail = previous.decompress_lapped(&[])?.1;
+            }
+            let frame = read_frame(reader, &header, entry)?;
+            let (samples, tail) = frame.decompress_lapped(&previous_tail)?;
+            extend_with_range(&mut out, &samples, entry.sample_offset as usize, start, end);
+            previous_tail = tail;
+        }
+        Ok(out)
+    }
+
+    /// Appends the frames of this stream to the stream stored in the file at `path`, the metadata of this stream is
+    /// ignored.
+    ///
+    /// The new frames and the new frame index are written over the old frame index, and only then the header is
+    /// rewritten in place with the new frame count and stream checksum. Until the header is rewritten readers only
+    /// see the frames that were already there, so the file stays readable if the process is killed mid-append.
+    /// If the header can't be rewritten in place (older layout, no usable index or a frame count that needs more
+    /// bytes) the whole stream is written to a temporary file that then replaces the original one.
+    pub fn append_to_file<P: AsRef<Path>>(mut self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        if self.data_frames.is_empty() {
+            return Ok(());
+        }
         let config = BinConfig::get();
-        let (compressed_stream, _) = bincode::decode_from_slice(data, config).unwrap();
-        compressed_stream
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut header: CompressorHeader = bincode::decode_from_std_read(&mut file, config)?;
+        header.check()?;
+        let header_size = file.stream_position()?;
+        let index = if header.version() == FORMAT_VERSION
+            && header.has_features(FEATURE_CHECKSUMS | FEATURE_INDEX)
+        {
+            read_index(&mut file, &header)?
+        } else {
+            None
+        };
+        let Some(mut index) = index else {
+            debug!("Stream can't be appended in place, rewriting it");
+            return self.append_by_rewrite(path, file);
+        };
+        if header.has_features(FEATURE_LAPPED) {
+            self.data_frames.iter_mut().for_each(|f| f.set_lapped());
+        } else if self.data_frames.iter().any(|f| f.is_lapped()) {
+            debug!("Lapped frames change the layout of the stream, rewriting it");
+            return self.append_by_rewrite(path, file);
+        }
+        // Frames end where the index starts
+        let frames_end = file.seek(SeekFrom::End(0))? - index.to_bytes().len() as u64;
+        if let Some(last) = index.entries().last() {
+            if last.offset + last.size != frames_end {
+                debug!("Frame index doesn't end where the frames do, rewriting the stream");
+                return self.append_by_rewrite(path, file);
+            }
+        }
+        let mut stream_crc = Crc32::resume(header.stream_checksum());
+        let mut out = Vec::new();
+        for frame in self.data_frames.iter() {
+            let offset = frames_end + out.len() as u64;
+            out.extend(bincode::encode_to_vec(frame.to_bytes(), config).unwrap());
+            out.extend(frame.checksum().to_le_bytes());
+            index.push(
+                offset,
+                frames_end + out.len() as u64 - offset,
+                frame.sample_count() as u64,
+            );
+            stream_crc.update(&frame.checksum().to_le_bytes());
+        }
+        out.extend(index.to_bytes());
+        header.upgrade(index.entries().len());
+        if self.data_frames.iter().any(|f| f.has_exceptions()) {
+            header.set_feature(FEATURE_EXCEPTIONS, true);
+        }
+        if self.data_frames.iter().any(|f| f.has_residuals()) {
+            header.set_feature(FEATURE_RESIDUALS, true);
+        }
+        header.set_stream_checksum(stream_crc.finalize());
+        let header_bytes = bincode::encode_to_vec(&header, config).unwrap();
+        if header_bytes.len() as u64 != header_size {
+            debug!("Frame count needs more bytes, rewriting the stream");
+            return self.append_by_rewrite(path, file);
+        }
+        file.seek(SeekFrom::Start(frames_end))?;
+        file.write_all(&out)?;
+        file.sync_data()?;
+        // Commit the new frames
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header_bytes)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Appends the frames of this stream by writing the whole stream to a temporary file that replaces `path`
+    fn append_by_rewrite(self, path: &Path, mut file: std::fs::File) -> Result<(), Error> {
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut data)?;
+        drop(file);
+        let mut stream = CompressedStream::from_bytes(&data)?;
+        for frame in self.data_frames {
+            stream.header.add_frame();
+            stream.data_frames.push(frame);
+        }
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+        temp.write_all(&stream.to_bytes())?;
+        temp.as_file().sync_data()?;
+        temp.persist(path).map_err(|e| e.error)?;
+        Ok(())
     }
-    pub fn decompress(&self) -> Vec<f64> {
-        self.data_frames
-            .iter()
-            .flat_map(|f| f.decompress())
-            .collect()
+
+    /// Decompresses the `len` samples starting at `start`, only the frames holding them (and, on lapped streams,
+    /// the frame before them) are decompressed
+    pub fn decompress_range(&self, start: usize, len: usize) -> Result<Vec<f64>, Error> {
+        let end = start.saturating_add(len);
+        let mut out = Vec::new();
+        let mut frame_start = 0;
+        let mut previous_tail = Vec::new();
+        for (i, frame) in self.data_frames.iter().enumerate() {
+            let frame_end = frame_start + frame.sample_count();
+            if frame_start < end && frame_end > start {
+                if previous_tail.is_empty() && i > 0 && self.data_frames[i - 1].tail() > 0 {
+                    previous_tail = self.data_frames[i - 1].decompress_lapped(&[])?.1;
+                }
+                let (samples, tail) = frame.decompress_lapped(&previous_tail)?;
+                extend_with_range(&mut out, &samples, frame_start, start, end);
+                previous_tail = tail;
+            }
+            frame_start = frame_end;
+        }
+        Ok(out)
+    }
+
+    pub fn decompress(&self) -> Result<Vec<f64>, Error> {
+        let mut out = Vec::new();
+        let mut previous_tail = Vec::new();
+        for frame in self.data_frames.iter() {
+            let (samples, tail) = frame.decompress_lapped(&previous_tail)?;
+            out.extend(samples);
+            previous_tail = tail;
+        }
+        Ok(out)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::error::calculate_error;
+    use std::io::Cursor;
+
+    /// Position where the frame index starts
+    fn frames_end(b: &[u8]) -> usize {
+        let (_, size) = FrameIndex::read_trailer(&b[b.len() - TRAILER_SIZE..]).unwrap();
+        b.len() - TRAILER_SIZE - size
+    }
+
+    fn ranged_stream() -> CompressedStream {
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_with(&[1.0, 2.0, 3.0, 4.0], Compressor::Noop)
+            .unwrap();
+        cs.compress_chunk_with(&[5.0, 6.0, 7.0, 8.0], Compressor::Noop)
+            .unwrap();
+        cs.compress_chunk_with(&[9.0, 10.0], Compressor::Noop)
+            .unwrap();
+        cs
+    }
 
     #[test]
     fn test_compress_chunk() {
         let vector1 = vec![1.0, 1.0, 1.0, 1.0, 1.0];
         let mut cs = CompressedStream::new();
-        cs.compress_chunk(&vector1);
+        cs.compress_chunk(&vector1).unwrap();
         assert_eq!(cs.data_frames.len(), 1);
     }
 
@@ -110,27 +718,367 @@ mod tests {
     fn test_compress_chunk_with() {
         let vector1 = vec![1.0, 1.0, 1.0, 1.0, 1.0];
         let mut cs = CompressedStream::new();
-        cs.compress_chunk_with(&vector1, Compressor::Constant);
+        cs.compress_chunk_with(&vector1, Compressor::Constant)
+            .unwrap();
         assert_eq!(cs.data_frames.len(), 1);
     }
 
+    #[test]
+    fn test_compress_best_lossless() {
+        let vector1: Vec<f64> = (0..600)
+            .map(|i| 20.0 + (i as f64 / 30.0).sin() * 0.125 + (i % 3) as f64 * 0.001)
+            .collect();
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_bounded_with(&vector1, Compressor::Auto, 0.0, 0)
+            .unwrap();
+        assert_eq!(cs.decompress().unwrap(), vector1);
+    }
+
+    #[test]
+    fn test_compress_best_integers() {
+        // Lossless, auto picks the delta compressor over the float ones
+        let vector1: Vec<f64> = (0..1024)
+            .map(|i| (1500 + i % 7 + (i / 100) * 3) as f64)
+            .collect();
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_bounded_with(&vector1, Compressor::Auto, 0.0, 0)
+            .unwrap();
+        assert_eq!(cs.decompress().unwrap(), vector1);
+        assert!(cs.to_bytes().len() < 1024);
+    }
+
+    #[test]
+    fn test_compress_best_inexact_integers() {
+        // Integral values a delta can't bring back exactly, lossless must still be exact
+        let vector1: Vec<f64> = (0..1024).map(|i| 1e19 + i as f64 * 4096.0).collect();
+        let vector2: Vec<f64> = vector1.iter().map(|v| -v).collect();
+        let vector3: Vec<f64> = (0..1024)
+            .map(|i| if i % 100 == 7 { -0.0 } else { (i % 13) as f64 })
+            .collect();
+        for vector in [vector1, vector2, vector3] {
+            let mut cs = CompressedStream::new();
+            cs.compress_chunk_bounded_with(&vector, Compressor::Auto, 0.0, 0)
+                .unwrap();
+            let out = cs.decompress().unwrap();
+            let bits = |d: &[f64]| d.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+            assert_eq!(bits(&out), bits(&vector));
+        }
+    }
+
+    #[test]
+    fn test_compress_best_counter() {
+        // A counter that resets half way, lossless it must come back exact and never go backwards
+        let vector1: Vec<f64> = (0..1000)
+            .map(|i| (i as f64 * 1.25).floor() - if i >= 500 { 600.0 } else { 0.0 } + 0.5)
+            .collect();
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_bounded_with(&vector1, Compressor::Auto, 0.0, 0)
+            .unwrap();
+        assert_eq!(cs.decompress().unwrap(), vector1);
+    }
+
+    #[test]
+    fn test_compress_best_rising() {
+        // Rising but with more decimals than the counter keeps, lossless must stay exact
+        let vector1: Vec<f64> = (0..2048).map(|i| 10.0 + i as f64 * 0.1234567891).collect();
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_bounded_with(&vector1, Compressor::Auto, 0.0, 0)
+            .unwrap();
+        assert_eq!(cs.decompress().unwrap(), vector1);
+        // With some error allowed the smooth trend goes to a curve, not to the counter
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_bounded_with(&vector1, Compressor::Auto, 0.01, 0)
+            .unwrap();
+        assert!(calculate_error(&vector1, &cs.decompress().unwrap()) <= 0.01);
+        assert!(cs.to_bytes().len() < 200);
+        // Too large for the counter to scale exactly
+        let vector2: Vec<f64> = (0..1024).map(|i| 1e16 + i as f64 * 4.0).collect();
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_bounded_with(&vector2, Compressor::Auto, 0.01, 0)
+            .unwrap();
+        assert!(calculate_error(&vector2, &cs.decompress().unwrap()) <= 0.01);
+    }
+
+    #[test]
+    fn test_compress_best_steps() {
+        let mut vector1 = vec![3.0; 2000];
+        vector1.extend(vec![5.0; 1000]);
+        vector1.extend(vec![4.0; 1096]);
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_bounded_with(&vector1, Compressor::Auto, 0.05, 0)
+            .unwrap();
+        assert_eq!(cs.decompress().unwrap(), vector1);
+        assert!(cs.to_bytes().len() < 100);
+    }
+
     #[test]
     fn test_to_bytes() {
         let vector1 = vec![1.0; 1024];
         let mut cs = CompressedStream::new();
-        cs.compress_chunk_with(&vector1, Compressor::Constant);
+        cs.compress_chunk_with(&vector1, Compressor::Constant)
+            .unwrap();
+        let b = cs.to_bytes();
+        assert_eq!(
+            b,
+            [
+                66, 82, 82, 79, 1, 3, 1, 49, 5, 65, 37, 52, 52, 251, 0, 4, 3, 3, 30, 3, 1, 0, 0, 0,
+                0, 0, 0, 240, 63, 0, 0, 0, 0, 0, 0, 240, 63, 0, 0, 0, 0, 0, 0, 144, 64, 251, 0, 4,
+                0, 0, 0, 0, 0, 0, 240, 63, 0, 0, 0, 0, 0, 0, 240, 63, 28, 161, 206, 141, 1, 11, 57,
+                0, 251, 0, 4, 214, 166, 17, 67, 7, 0, 0, 0, 66, 82, 73, 88
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stream_without_summaries() {
+        let b = [
+            66, 82, 82, 79, 1, 3, 1, 161, 33, 2, 143, 9, 9, 251, 0, 4, 3, 3, 30, 3, 1, 238, 208,
+            189, 108, 1, 11, 14, 0, 251, 0, 4, 64, 194, 16, 80, 7, 0, 0, 0, 66, 82, 73, 88,
+        ];
+        let cs = CompressedStream::from_bytes(&b).unwrap();
+        assert_eq!(cs.frame_summaries(), [None]);
+        assert!(cs.summary().is_none());
+        assert_eq!(cs.decompress().unwrap(), vec![1.0; 1024]);
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_with(&[3.0, 1.0, 2.0], Compressor::Noop)
+            .unwrap();
+        cs.compress_chunk_bounded_with(&[5.0, -1.0, 4.0, 4.5], Compressor::FFT, 0.1, 0)
+            .unwrap();
+        let cs = CompressedStream::from_bytes(&cs.to_bytes()).unwrap();
+        let summaries = cs.frame_summaries();
+        assert_eq!(
+            summaries[0],
+            Some(FrameSummary {
+                min: 1.0,
+                max: 3.0,
+                sum: 6.0,
+                count: 3,
+                first: 3.0,
+                last: 2.0,
+            })
+        );
+        // Exact, even if the frame is lossy
+        assert_eq!(summaries[1].unwrap().max, 5.0);
+        let summary = cs.summary().unwrap();
+        assert_eq!(summary.min, -1.0);
+        assert_eq!(summary.max, 5.0);
+        assert_eq!(summary.sum, 18.5);
+        assert_eq!(summary.count, 7);
+        assert_eq!(summary.first, 3.0);
+        assert_eq!(summary.last, 4.5);
+        assert!(CompressedStream::new().summary().is_none());
+    }
+
+    #[test]
+    fn test_non_finite_samples() {
+        let stale = f64::from_bits(0x7ff0000000000002);
+        let mut chunk: Vec<f64> = (0..512)
+            .map(|i| ((i as f64 / 10.0).sin() * 100.0).round() + 1000.0)
+            .collect();
+        chunk[0] = f64::NAN;
+        chunk[100..110].fill(stale);
+        chunk[300] = f64::INFINITY;
+        chunk[511] = f64::NEG_INFINITY;
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_with(&chunk, Compressor::Noop).unwrap();
+        cs.compress_chunk_bounded_with(&chunk, Compressor::Auto, 0.01, 0)
+            .unwrap();
+        cs.compress_chunk_with(&[1.0, 2.0, 3.0], Compressor::Noop)
+            .unwrap();
+        let cs = CompressedStream::from_bytes(&cs.to_bytes()).unwrap();
+        assert!(cs.header().has_features(FEATURE_EXCEPTIONS));
+        let out = cs.decompress().unwrap();
+        assert_eq!(out.len(), 512 * 2 + 3);
+        // Lossless frame is exact, special values keep their bits
+        let bits = |d: &[f64]| d.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&out[..512]), bits(&chunk));
+        let (mut finite, mut lossy) = (Vec::new(), Vec::new());
+        for (a, b) in chunk.iter().zip(out[512..1024].iter()) {
+            if a.is_finite() {
+                finite.push(*a);
+                lossy.push(*b);
+            } else {
+                assert_eq!(a.to_bits(), b.to_bits());
+            }
+        }
+        assert_eq!(finite.len(), 512 - 13);
+        assert!(calculate_error(&finite, &lossy) <= 0.01);
+        assert_eq!(out[1024..], [1.0, 2.0, 3.0]);
+        let summary = cs.frame_summaries()[0].unwrap();
+        assert_eq!(summary.count, 512 - 13);
+        assert_eq!(summary.first, chunk[1]);
+        assert_eq!(summary.last, chunk[510]);
+        assert!(
+            !CompressedStream::from_bytes(&chunk_stream(&[&[1.0]]).to_bytes())
+                .unwrap()
+                .header()
+                .has_features(FEATURE_EXCEPTIONS)
+        );
+    }
+
+    #[test]
+    fn test_residuals() {
+        let chunk: Vec<f64> = (0..1000)
+            .map(|i| ((i as f64 / 40.0).sin() * 50.0 + 100.0 + (i % 3) as f64 * 0.1).round() / 10.0)
+            .collect();
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_with_residuals(&chunk, Compressor::FFT, 0.0)
+            .unwrap();
+        cs.compress_chunk_with_residuals(&chunk, Compressor::Polynomial, 0.5)
+            .unwrap();
+        // Auto keeps the smallest model for each frame, a frame left as Auto would not decompress
+        cs.compress_chunk_with_residuals(&chunk, Compressor::Auto, 0.5)
+            .unwrap();
+        assert!(matches!(
+            cs.compress_chunk_with_residuals(&chunk, Compressor::Noop, 0.0),
+            Err(Error::UnsupportedCompressor(Compressor::Noop))
+        ));
+        let cs = CompressedStream::from_bytes(&cs.to_bytes()).unwrap();
+        assert!(cs.header().has_features(FEATURE_RESIDUALS));
+        let out = cs.decompress().unwrap();
+        assert_eq!(out[..1000], chunk);
+        for (original, restored) in chunk.iter().cycle().zip(out[1000..].iter()) {
+            assert!((original - restored).abs() <= 0.25 + 1e-9);
+        }
+        // Model compressors compete with the lossless ones at an error of 0
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_bounded_with(&chunk, Compressor::Auto, 0.0, 0)
+            .unwrap();
+        assert_eq!(cs.decompress().unwrap(), chunk);
+        assert!(!chunk_stream(&[&chunk]).to_bytes().is_empty());
+    }
+
+    #[test]
+    fn test_lapped() {
+        let data: Vec<f64> = (0..2048)
+            .map(|i| 100.0 + i as f64 * 0.05 + (i as f64 / 20.0).sin() * 10.0)
+            .collect();
+        // Largest step between consecutive samples
+        let max_step = |out: &[f64], range: std::ops::Range<usize>| {
+            range
+                .map(|i| (out[i + 1] - out[i]).abs())
+                .fold(0.0, f64::max)
+        };
+        let mut plain = CompressedStream::new();
+        let mut lapped = CompressedStream::new();
+        for (start, tail) in [(0, 64), (1024, 0)] {
+            let chunk = &data[start..start + 1024];
+            plain
+                .compress_chunk_bounded_with(chunk, Compressor::FFT, 0.01, 0)
+                .unwrap();
+            lapped
+                .compress_chunk_lapped(
+                    &data[start..start + 1024 + tail],
+                    tail,
+                    Compressor::FFT,
+                    0.01,
+                    0,
+                )
+                .unwrap();
+        }
+        let plain_size = plain.clone().to_bytes().len();
+        let plain = plain.decompress().unwrap();
+        let b = lapped.to_bytes();
+        let cs = CompressedStream::from_bytes(&b).unwrap();
+        assert!(cs.header().has_features(FEATURE_LAPPED));
+        let out = cs.decompress().unwrap();
+        assert_eq!(out.len(), 2048);
+        assert!(b.len() * 10 <= plain_size * 11);
+        // Frames meeting leave a step, lapped frames are as continuous there as anywhere else
+        assert!(max_step(&plain, 1020..1027) > max_step(&plain, 100..900) * 2.0);
+        assert!(max_step(&out, 1020..1100) <= max_step(&out, 100..900));
+        assert!(calculate_error(&data, &out) <= 0.01);
+        assert_eq!(cs.decompress_range(1000, 100).unwrap(), out[1000..1100]);
+        assert_eq!(
+            CompressedStream::read_range(&mut Cursor::new(&b), 1030, 10).unwrap(),
+            out[1030..1040]
+        );
+    }
+
+    #[test]
+    fn test_append_lapped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("append.bro");
+        let chunk: Vec<f64> = (0..600).map(|i| (i as f64 / 30.0).sin() * 10.0).collect();
+        let mut residuals = CompressedStream::new();
+        residuals
+            .compress_chunk_with_residuals(&chunk, Compressor::FFT, 0.0)
+            .unwrap();
+        std::fs::write(&path, residuals.clone().to_bytes()).unwrap();
+        // Lapped frames change the layout, the stream is rewritten
+        let mut lapped = CompressedStream::new();
+        lapped
+            .compress_chunk_lapped(&chunk, 100, Compressor::FFT, 0.01, 0)
+            .unwrap();
+        lapped
+            .compress_chunk_lapped(&chunk[500..], 0, Compressor::FFT, 0.01, 0)
+            .unwrap();
+        lapped.append_to_file(&path).unwrap();
+        // Frames appended to a lapped stream take its layout
+        residuals.append_to_file(&path).unwrap();
+        let b = std::fs::read(&path).unwrap();
+        assert!(CompressedStream::verify(&b).unwrap().is_ok());
+        let cs = CompressedStream::from_bytes(&b).unwrap();
+        assert!(cs.header().has_features(FEATURE_LAPPED | FEATURE_RESIDUALS));
+        let out = cs.decompress().unwrap();
+        assert_eq!(out.len(), 600 * 3);
+        assert_eq!(out[..600], chunk);
+        assert_eq!(out[1200..], chunk);
+    }
+
+    #[test]
+    fn test_many_frames() {
+        // Way past what an i16 frame count could hold
+        let frame_count = 300_000;
+        let mut cs = CompressedStream::new();
+        for i in 0..frame_count {
+            cs.compress_chunk_with(&[i as f64], Compressor::Noop)
+                .unwrap();
+        }
+        assert_eq!(cs.header().frame_count(), frame_count);
         let b = cs.to_bytes();
-        assert_eq!(b, [66, 82, 82, 79, 0, 1, 41, 251, 0, 4, 3, 3, 30, 3, 1]);
+        let report = CompressedStream::verify(&b).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.frame_count, frame_count);
+        let cs = CompressedStream::from_bytes(&b).unwrap();
+        assert_eq!(cs.header().frame_count(), frame_count);
+        let out = cs.decompress().unwrap();
+        assert_eq!(out.len(), frame_count);
+        assert_eq!(out[frame_count - 1], (frame_count - 1) as f64);
+        let mut reader = Cursor::new(&b);
+        assert_eq!(
+            CompressedStream::read_range(&mut reader, frame_count - 3, 10).unwrap(),
+            [299_997.0, 299_998.0, 299_999.0]
+        );
+    }
+
+    #[test]
+    fn test_corrupted_frame_count() {
+        let mut b = ranged_stream().to_bytes();
+        // Frame count is the last byte of the header, make it a huge varint (252 + u32)
+        let mut corrupted = b[..6].to_vec();
+        corrupted.extend([252, 255, 255, 255, 255]);
+        corrupted.extend(b.split_off(7));
+        assert!(matches!(
+            CompressedStream::from_bytes(&corrupted),
+            Err(Error::TruncatedInput)
+        ));
+        assert!(CompressedStream::verify(&corrupted).is_err());
     }
 
     #[test]
     fn test_from_bytes() {
         let vector1 = vec![1.0; 1024];
         let mut cs = CompressedStream::new();
-        cs.compress_chunk_with(&vector1, Compressor::Constant);
+        cs.compress_chunk_with(&vector1, Compressor::Constant)
+            .unwrap();
         let len = cs.data_frames.len();
         let b = cs.to_bytes();
-        let cs2 = CompressedStream::from_bytes(&b);
+        let cs2 = CompressedStream::from_bytes(&b).unwrap();
         assert_eq!(len, cs2.data_frames.len());
     }
 
@@ -138,10 +1086,314 @@ mod tests {
     fn test_constant_decompression() {
         let vector1 = vec![1.0; 1024];
         let mut cs = CompressedStream::new();
-        cs.compress_chunk_with(&vector1, Compressor::Constant);
+        cs.compress_chunk_with(&vector1, Compressor::Constant)
+            .unwrap();
         let b = cs.to_bytes();
-        let cs2 = CompressedStream::from_bytes(&b);
-        let out = cs2.decompress();
+        let cs2 = CompressedStream::from_bytes(&b).unwrap();
+        let out = cs2.decompress().unwrap();
         assert_eq!(vector1, out);
     }
+
+    #[test]
+    fn test_legacy_stream() {
+        // Stream written before the header was versioned
+        let b = [66, 82, 82, 79, 0, 1, 41, 251, 0, 4, 3, 3, 30, 3, 1];
+        let cs = CompressedStream::from_bytes(&b).unwrap();
+        assert_eq!(cs.header().version(), 0);
+        assert_eq!(cs.header().frame_count(), 1);
+        assert_eq!(cs.decompress().unwrap(), vec![1.0; 1024]);
+        // Written back in the current layout, with checksums
+        let b = cs.to_bytes();
+        assert!(CompressedStream::verify(&b).unwrap().is_ok());
+        let cs2 = CompressedStream::from_bytes(&b).unwrap();
+        assert_eq!(cs2.header().version(), FORMAT_VERSION);
+        assert_eq!(cs2.decompress().unwrap(), vec![1.0; 1024]);
+    }
+
+    #[test]
+    fn test_newer_version_rejected() {
+        let b = [66, 82, 82, 79, 99, 0, 2, 1, 41, 251, 0, 4, 3, 3, 30, 3, 1];
+        assert!(CompressedStream::from_bytes(&b).is_err());
+    }
+
+    #[test]
+    fn test_empty_chunk() {
+        let mut cs = CompressedStream::new();
+        assert!(matches!(cs.compress_chunk(&[]), Err(Error::EmptyInput)));
+        assert!(matches!(
+            cs.compress_chunk_bounded_with(&[], Compressor::Auto, 0.01, 0),
+            Err(Error::EmptyInput)
+        ));
+        assert_eq!(cs.header().frame_count(), 0);
+    }
+
+    #[test]
+    fn test_garbage_stream() {
+        assert!(matches!(
+            CompressedStream::from_bytes(&[66, 82, 82]),
+            Err(Error::TruncatedInput)
+        ));
+        assert!(CompressedStream::from_bytes(&[1, 2, 3, 4, 5, 6, 7, 8]).is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_with(&[1.0, 2.0, 3.0], Compressor::Noop)
+            .unwrap();
+        cs.compress_chunk_with(&[4.0, 5.0, 6.0], Compressor::Noop)
+            .unwrap();
+        let b = cs.to_bytes();
+        let report = CompressedStream::verify(&b).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.frame_count, 2);
+    }
+
+    #[test]
+    fn test_corrupted_frame() {
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_with(&[1.0, 2.0, 3.0], Compressor::Noop)
+            .unwrap();
+        cs.compress_chunk_with(&[4.0, 5.0, 6.0], Compressor::Noop)
+            .unwrap();
+        let mut b = cs.to_bytes();
+        // Flip a bit in the last sample of the second frame, right before its checksum
+        let pos = frames_end(&b) - 5;
+        b[pos] ^= 0x01;
+        assert!(CompressedStream::from_bytes(&b).is_err());
+        let report = CompressedStream::verify(&b).unwrap();
+        assert_eq!(report.corrupt_frames, [1]);
+        assert!(report.stream_checksum_ok);
+    }
+
+    #[test]
+    fn test_truncated_stream() {
+        let mut cs = CompressedStream::new();
+        cs.compress_chunk_with(&[1.0, 2.0, 3.0], Compressor::Noop)
+            .unwrap();
+        cs.compress_chunk_with(&[4.0, 5.0, 6.0], Compressor::Noop)
+            .unwrap();
+        let b = cs.to_bytes();
+        let truncated = &b[..frames_end(&b) - 6];
+        assert!(CompressedStream::from_bytes(truncated).is_err());
+        let report = CompressedStream::verify(truncated).unwrap();
+        assert_eq!(report.corrupt_frames, [1]);
+        assert!(!report.stream_checksum_ok);
+    }
+
+    #[test]
+    fn test_decompress_range() {
+        let cs = ranged_stream();
+        assert_eq!(cs.decompress_range(0, 2).unwrap(), [1.0, 2.0]);
+        assert_eq!(cs.decompress_range(3, 3).unwrap(), [4.0, 5.0, 6.0]);
+        assert_eq!(cs.decompress_range(7, 100).unwrap(), [8.0, 9.0, 10.0]);
+        assert!(cs.decompress_range(10, 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_range() {
+        let b = ranged_stream().to_bytes();
+        let mut reader = Cursor::new(&b);
+        assert_eq!(
+            CompressedStream::read_range(&mut reader, 3, 3).unwrap(),
+            [4.0, 5.0, 6.0]
+        );
+        assert_eq!(
+            CompressedStream::read_range(&mut reader, 8, 2).unwrap(),
+            [9.0, 10.0]
+        );
+        assert_eq!(
+            CompressedStream::read_range(&mut reader, 0, usize::MAX).unwrap(),
+            CompressedStream::from_bytes(&b)
+                .unwrap()
+                .decompress()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read_range_damaged_index() {
+        let mut b = ranged_stream().to_bytes();
+        // Damage the index, the reader should go through the whole stream instead
+        let pos = b.len() - TRAILER_SIZE - 2;
+        b[pos] ^= 0x01;
+        let mut reader = Cursor::new(&b);
+        assert_eq!(
+            CompressedStream::read_range(&mut reader, 3, 3).unwrap(),
+            [4.0, 5.0, 6.0]
+        );
+    }
+
+    #[test]
+    fn test_read_range_legacy() {
+        let b = [66, 82, 82, 79, 0, 1, 41, 251, 0, 4, 3, 3, 30, 3, 1];
+        let mut reader = Cursor::new(&b);
+        assert_eq!(
+            CompressedStream::read_range(&mut reader, 1020, 10).unwrap(),
+            [1.0; 4]
+        );
+    }
+
+    fn metadata_stream() -> CompressedStream {
+        let mut cs = ranged_stream();
+        let mut metadata = Metadata::new();
+        metadata.metric_name = Some("temperature".to_string());
+        metadata.unit = Some("celsius".to_string());
+        metadata
+            .labels
+            .insert("room".to_string(), "kitchen".to_string());
+        cs.set_metadata(metadata);
+        cs
+    }
+
+    #[test]
+    fn test_metadata_roundtrip() {
+        let cs = metadata_stream();
+        let metadata = cs.metadata().cloned();
+        let b = cs.to_bytes();
+        let cs2 = CompressedStream::from_bytes(&b).unwrap();
+        assert!(cs2.header().has_features(FEATURE_METADATA));
+        assert_eq!(cs2.metadata().cloned(), metadata);
+        assert_eq!(cs2.decompress().unwrap().len(), 10);
+        assert!(CompressedStream::verify(&b).unwrap().is_ok());
+        let mut reader = Cursor::new(&b);
+        assert_eq!(
+            CompressedStream::read_range(&mut reader, 3, 3).unwrap(),
+            [4.0, 5.0, 6.0]
+        );
+    }
+
+    #[test]
+    fn test_read_metadata() {
+        let mut b = metadata_stream().to_bytes();
+        // Frames are not read, so a broken frame doesn't matter
+        let pos = frames_end(&b) - 5;
+        b[pos] ^= 0x01;
+        let metadata = CompressedStream::read_metadata(&mut Cursor::new(&b))
+            .unwrap()
+            .unwrap();
+        assert_eq!(metadata.metric_name.as_deref(), Some("temperature"));
+        assert_eq!(metadata.labels["room"], "kitchen");
+        // No metadata
+        let b = ranged_stream().to_bytes();
+        assert!(CompressedStream::read_metadata(&mut Cursor::new(&b))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_corrupted_metadata() {
+        let mut b = metadata_stream().to_bytes();
+        // The metadata block starts right after the 11 bytes of the header and its 1 byte length
+        b[13] ^= 0x01;
+        assert!(CompressedStream::from_bytes(&b).is_err());
+        assert!(CompressedStream::read_metadata(&mut Cursor::new(&b)).is_err());
+        let report = CompressedStream::verify(&b).unwrap();
+        assert!(!report.metadata_ok);
+        assert!(report.corrupt_frames.is_empty());
+    }
+
+    fn chunk_stream(chunks: &[&[f64]]) -> CompressedStream {
+        let mut cs = CompressedStream::new();
+        for chunk in chunks {
+            cs.compress_chunk_with(chunk, Compressor::Noop).unwrap();
+        }
+        cs
+    }
+
+    #[test]
+    fn test_append_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("append.bro");
+        std::fs::write(&path, metadata_stream().to_bytes()).unwrap();
+        let header_size = bincode::encode_to_vec(CompressorHeader::new(), BinConfig::get())
+            .unwrap()
+            .len();
+        let original = std::fs::read(&path).unwrap();
+        chunk_stream(&[&[11.0, 12.0], &[13.0]])
+            .append_to_file(&path)
+            .unwrap();
+        let b = std::fs::read(&path).unwrap();
+        // Frames already in the file are untouched
+        assert_eq!(
+            b[header_size..frames_end(&original)],
+            original[header_size..frames_end(&original)]
+        );
+        assert!(CompressedStream::verify(&b).unwrap().is_ok());
+        let cs = CompressedStream::from_bytes(&b).unwrap();
+        assert_eq!(cs.header().frame_count(), 5);
+        assert_eq!(
+            cs.metadata().unwrap().metric_name.as_deref(),
+            Some("temperature")
+        );
+        assert_eq!(
+            cs.decompress().unwrap(),
+            [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 13.0]
+        );
+        let mut reader = std::fs::File::open(&path).unwrap();
+        assert_eq!(
+            CompressedStream::read_range(&mut reader, 9, 3).unwrap(),
+            [10.0, 11.0, 12.0]
+        );
+    }
+
+    #[test]
+    fn test_append_interrupted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("append.bro");
+        let original = ranged_stream().to_bytes();
+        std::fs::write(&path, &original).unwrap();
+        chunk_stream(&[&[11.0, 12.0]])
+            .append_to_file(&path)
+            .unwrap();
+        let appended = std::fs::read(&path).unwrap();
+        let header_size = 11;
+        // Killed before the header was rewritten: new frames and index written, old header
+        let mut killed = original[..header_size].to_vec();
+        killed.extend(&appended[header_size..]);
+        // Killed while writing the new frames
+        let partial = &killed[..frames_end(&original) + 3];
+        for b in [&killed[..], partial] {
+            assert!(CompressedStream::verify(b).unwrap().is_ok());
+            let cs = CompressedStream::from_bytes(b).unwrap();
+            assert_eq!(cs.decompress().unwrap().len(), 10);
+            let mut reader = Cursor::new(b);
+            assert_eq!(
+                CompressedStream::read_range(&mut reader, 8, 10).unwrap(),
+                [9.0, 10.0]
+            );
+            // And it can still be appended to
+            std::fs::write(&path, b).unwrap();
+            chunk_stream(&[&[11.0, 12.0]])
+                .append_to_file(&path)
+                .unwrap();
+            let cs = CompressedStream::from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+            assert_eq!(cs.decompress().unwrap().len(), 12);
+        }
+    }
+
+    #[test]
+    fn test_append_rewrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("append.bro");
+        // Older layout, can't be appended in place
+        let b = [66, 82, 82, 79, 0, 1, 41, 251, 0, 4, 3, 3, 30, 3, 1];
+        std::fs::write(&path, b).unwrap();
+        chunk_stream(&[&[2.0]]).append_to_file(&path).unwrap();
+        let cs = CompressedStream::from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(cs.header().version(), FORMAT_VERSION);
+        assert_eq!(cs.decompress().unwrap().len(), 1025);
+        // The frame count needs one more byte past 250 frames
+        let chunks: Vec<[f64; 1]> = (0..250).map(|i| [i as f64]).collect();
+        let chunks: Vec<&[f64]> = chunks.iter().map(|c| &c[..]).collect();
+        std::fs::write(&path, chunk_stream(&chunks).to_bytes()).unwrap();
+        chunk_stream(&[&[250.0]]).append_to_file(&path).unwrap();
+        let b = std::fs::read(&path).unwrap();
+        assert!(CompressedStream::verify(&b).unwrap().is_ok());
+        let out = CompressedStream::from_bytes(&b)
+            .unwrap()
+            .decompress()
+            .unwrap();
+        assert_eq!(out, (0..251).map(|i| i as f64).collect::<Vec<f64>>());
+    }
 }
@@ -0,0 +1,64 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::compressor::Compressor;
+use bincode::error::DecodeError;
+
+/// Errors returned by the compression and decompression entry points
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Corrupted data: {0}")]
+    CorruptData(String),
+
+    #[error("Unsupported compressor: {0:?}")]
+    UnsupportedCompressor(Compressor),
+
+    #[error("Truncated input, more data was expected")]
+    TruncatedInput,
+
+    #[error("Empty input, there is nothing to compress")]
+    EmptyInput,
+
+    #[error("Values out of range: {0}")]
+    OutOfRange(String),
+
+    #[error("Unsupported format: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Series not found: {0}")]
+    SeriesNotFound(String),
+
+    #[error("Series already exists: {0}")]
+    DuplicateSeries(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Self {
+        match err {
+            DecodeError::UnexpectedEnd { .. } => Error::TruncatedInput,
+            DecodeError::Io { inner, .. } if inner.kind() == std::io::ErrorKind::UnexpectedEof => {
+                Error::TruncatedInput
+            }
+            DecodeError::Io { inner, .. } => Error::Io(inner),
+            _ => Error::CorruptData(err.to_string()),
+        }
+    }
+}
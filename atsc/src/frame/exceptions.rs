@@ -0,0 +1,240 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::error::Error;
+use bincode::{Decode, Encode};
+
+/// Tags of the values a run can hold, anything that isn't a plain NaN or an infinity (e.g. the Prometheus stale
+/// marker, a NaN with a payload) is stored with its bits.
+const NAN: u8 = 0;
+const INFINITY: u8 = 1;
+const NEG_INFINITY: u8 = 2;
+const BITS: u8 = 3;
+
+/// A run of consecutive samples holding the same non-finite value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExceptionRun {
+    pub start: usize,
+    pub len: usize,
+    /// Bits of the value, NaNs are compared by their bits so payloads survive
+    pub bits: u64,
+}
+
+/// Positions of the samples of a frame that compressors can't handle (NaN, +Inf and -Inf).
+///
+/// Missing samples are usually written as NaN, they are kept in a sparse list of runs so the decompressed frame
+/// has them back in the same place and every other sample stays aligned with its timestamp.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Exceptions {
+    runs: Vec<ExceptionRun>,
+}
+
+// Runs are stored as the distance from the end of the previous one, their length and the value tag, all varints
+impl Encode for Exceptions {
+    fn encode<__E: ::bincode::enc::Encoder>(
+        &self,
+        encoder: &mut __E,
+    ) -> Result<(), ::bincode::error::EncodeError> {
+        Encode::encode(&(self.runs.len() as u64), encoder)?;
+        let mut end = 0;
+        for run in self.runs.iter() {
+            Encode::encode(&((run.start - end) as u64), encoder)?;
+            Encode::encode(&(run.len as u64), encoder)?;
+            let value = f64::from_bits(run.bits);
+            if value == f64::INFINITY {
+                Encode::encode(&INFINITY, encoder)?;
+            } else if value == f64::NEG_INFINITY {
+                Encode::encode(&NEG_INFINITY, encoder)?;
+            } else if run.bits == f64::NAN.to_bits() {
+                Encode::encode(&NAN, encoder)?;
+            } else {
+                Encode::encode(&BITS, encoder)?;
+                Encode::encode(&run.bits, encoder)?;
+            }
+            end = run.start + run.len;
+        }
+        Ok(())
+    }
+}
+
+impl Decode for Exceptions {
+    fn decode<__D: ::bincode::de::Decoder>(
+        decoder: &mut __D,
+    ) -> Result<Self, ::bincode::error::DecodeError> {
+        let count: u64 = Decode::decode(decoder)?;
+        let mut runs = Vec::new();
+        let mut end: u64 = 0;
+        for _ in 0..count {
+            let gap: u64 = Decode::decode(decoder)?;
+            let len: u64 = Decode::decode(decoder)?;
+            let tag: u8 = Decode::decode(decoder)?;
+            let bits = match tag {
+                NAN => f64::NAN.to_bits(),
+                INFINITY => f64::INFINITY.to_bits(),
+                NEG_INFINITY => f64::NEG_INFINITY.to_bits(),
+                BITS => Decode::decode(decoder)?,
+                _ => {
+                    return Err(::bincode::error::DecodeError::OtherString(format!(
+                        "unknown exception value tag {}",
+                        tag
+                    )))
+                }
+            };
+            let out_of_range =
+                || ::bincode::error::DecodeError::Other("exception run out of range");
+            let start = end.checked_add(gap).ok_or_else(out_of_range)?;
+            end = start.checked_add(len).ok_or_else(out_of_range)?;
+            runs.push(ExceptionRun {
+                start: start as usize,
+                len: len as usize,
+                bits,
+            });
+        }
+        Ok(Exceptions { runs })
+    }
+}
+
+bincode::impl_borrow_decode!(Exceptions);
+
+impl Exceptions {
+    /// Finds the non-finite samples of `data`
+    pub fn find(data: &[f64]) -> Self {
+        let mut runs: Vec<ExceptionRun> = Vec::new();
+        for (i, value) in data.iter().enumerate() {
+            if value.is_finite() {
+                continue;
+            }
+            match runs.last_mut() {
+                Some(run) if run.start + run.len == i && run.bits == value.to_bits() => {
+                    run.len += 1
+                }
+                _ => runs.push(ExceptionRun {
+                    start: i,
+                    len: 1,
+                    bits: value.to_bits(),
+                }),
+            }
+        }
+        Exceptions { runs }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    pub fn runs(&self) -> &[ExceptionRun] {
+        &self.runs
+    }
+
+    /// Puts the non-finite values back in the decompressed samples
+    pub fn restore(&self, data: &mut [f64]) -> Result<(), Error> {
+        let sample_count = data.len();
+        for run in self.runs.iter() {
+            let samples = data
+                .get_mut(run.start..run.start + run.len)
+                .ok_or_else(|| {
+                    Error::CorruptData(format!(
+                        "exception run at {} is outside the {} samples of the frame",
+                        run.start, sample_count
+                    ))
+                })?;
+            samples.fill(f64::from_bits(run.bits));
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the non-finite samples so the data can be fed to any compressor. Gaps are filled with a line between
+/// the finite samples around them, so lossy compressors fit across them instead of around a spike, gaps at the
+/// edges repeat the closest finite sample. If there is no finite sample at all, the result is all zeros.
+pub fn fill_gaps(data: &[f64]) -> Vec<f64> {
+    let mut filled = data.to_vec();
+    let mut previous: Option<usize> = None;
+    let mut i = 0;
+    while i < filled.len() {
+        if filled[i].is_finite() {
+            previous = Some(i);
+            i += 1;
+            continue;
+        }
+        let gap_end = (i..filled.len())
+            .find(|&j| filled[j].is_finite())
+            .unwrap_or(filled.len());
+        let next = filled.get(gap_end).copied();
+        for j in i..gap_end {
+            filled[j] = match (previous.map(|p| (p, filled[p])), next) {
+                (Some((p, before)), Some(after)) => {
+                    let value = before + (after - before) * (j - p) as f64 / (gap_end - p) as f64;
+                    // Keep integer series integer, the compressors pick a smaller bitdepth for them
+                    if before.fract() == 0.0 && after.fract() == 0.0 {
+                        value.round()
+                    } else {
+                        value
+                    }
+                }
+                (Some((_, before)), None) => before,
+                (None, Some(after)) => after,
+                (None, None) => 0.0,
+            };
+        }
+        i = gap_end;
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::BinConfig;
+
+    #[test]
+    fn test_find_and_restore() {
+        let stale = f64::from_bits(0x7ff0000000000002);
+        let data = [
+            1.0,
+            f64::NAN,
+            f64::NAN,
+            4.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            stale,
+        ];
+        let exceptions = Exceptions::find(&data);
+        assert_eq!(exceptions.runs().len(), 4);
+        assert_eq!(exceptions.runs()[0].len, 2);
+        let config = BinConfig::get();
+        let bytes = bincode::encode_to_vec(&exceptions, config).unwrap();
+        assert_eq!(bytes[..10], [4, 1, 2, 0, 1, 1, 1, 0, 1, 2]);
+        let (decoded, _): (Exceptions, usize) = bincode::decode_from_slice(&bytes, config).unwrap();
+        assert_eq!(decoded, exceptions);
+
+        let mut restored = fill_gaps(&data);
+        decoded.restore(&mut restored).unwrap();
+        let bits = |d: &[f64]| d.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&restored), bits(&data));
+        assert!(decoded.restore(&mut restored[..5]).is_err());
+    }
+
+    #[test]
+    fn test_fill_gaps() {
+        assert_eq!(
+            fill_gaps(&[f64::NAN, 1.0, f64::NAN, f64::NAN, 4.0, f64::INFINITY]),
+            [1.0, 1.0, 2.0, 3.0, 4.0, 4.0]
+        );
+        assert_eq!(fill_gaps(&[0.5, f64::NAN, 1.0]), [0.5, 0.75, 1.0]);
+        assert_eq!(fill_gaps(&[f64::NAN, f64::NAN]), [0.0, 0.0]);
+    }
+}
@@ -14,24 +14,185 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use crate::{compressor::Compressor, optimizer::utils::DataStats};
+use crate::{
+    compressor::{
+        counter::{counter_decimals, is_counter},
+        delta::fits_delta,
+        rle::run_count,
+        BinConfig, Compressor, CompressorResult,
+    },
+    error::Error,
+    optimizer::utils::DataStats,
+    utils::{checksum::crc32, error::calculate_error},
+};
 use bincode::{Decode, Encode};
+use exceptions::{fill_gaps, Exceptions};
 use log::debug;
-use std::mem::size_of_val;
+use residuals::Residuals;
+use std::borrow::Cow;
+use std::f64::consts::FRAC_PI_2;
+
+pub mod exceptions;
+pub mod residuals;
 
 const COMPRESSION_SPEED: [i32; 7] = [i32::MAX, 4096, 2048, 1024, 512, 256, 128];
+/// The run length compressor is only tried on frames with, on average, runs of this many samples or longer
+const MAX_RUNS_RATIO: usize = 16;
+
+/// Crossfades the first samples of a frame with the tail of the previous frame, that models the same samples.
+/// The weight of the frame rises from 0 to 1 along the tail on a Hann half window (`sin²`), so the output follows
+/// the previous frame where the frame starts and the frame itself where the tail ends, and neither frame edge shows.
+/// Samples both frames agree on, and non-finite ones, are kept as they are.
+fn crossfade(head: &mut [f64], previous_tail: &[f64]) {
+    let len = previous_tail.len() as f64;
+    for (k, (value, previous)) in head.iter_mut().zip(previous_tail.iter()).enumerate() {
+        if *value == *previous || !value.is_finite() || !previous.is_finite() {
+            continue;
+        }
+        let weight = (FRAC_PI_2 * (k as f64 + 0.5) / len).sin().powi(2);
+        *value = previous + weight * (*value - previous);
+    }
+}
+
+/// Summary of the samples of a frame. It is calculated over the original samples when the frame is compressed, so
+/// it is exact even for lossy compressors, and reading it doesn't need the frame to be decompressed.
+/// Non-finite samples are left out of it, a frame without any finite sample has a count of 0.
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq)]
+pub struct FrameSummary {
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub count: u64,
+    pub first: f64,
+    pub last: f64,
+}
+
+impl FrameSummary {
+    /// Summary of the finite samples of `data`
+    pub fn new(data: &[f64]) -> Self {
+        let mut summary = FrameSummary {
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.0,
+            count: 0,
+            first: f64::NAN,
+            last: f64::NAN,
+        };
+        for &value in data.iter().filter(|v| v.is_finite()) {
+            if summary.count == 0 {
+                summary.first = value;
+            }
+            summary.min = summary.min.min(value);
+            summary.max = summary.max.max(value);
+            summary.sum += value;
+            summary.count += 1;
+            summary.last = value;
+        }
+        summary
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+
+    /// Summary of the samples of this frame followed by the samples of `next`
+    pub fn merge(&self, next: &FrameSummary) -> FrameSummary {
+        FrameSummary {
+            min: self.min.min(next.min),
+            max: self.max.max(next.max),
+            sum: self.sum + next.sum,
+            count: self.count + next.count,
+            first: if self.count == 0 {
+                next.first
+            } else {
+                self.first
+            },
+            last: if next.count == 0 {
+                self.last
+            } else {
+                next.last
+            },
+        }
+    }
+}
+
+/// A compressor output competing to be picked by `compress_best`
+struct Candidate {
+    compressor: Compressor,
+    result: CompressorResult,
+    residuals: Option<Residuals>,
+}
+
+impl Candidate {
+    /// Bytes taken by the compressor output and the residuals
+    fn size(&self) -> usize {
+        let residuals_size = self.residuals.as_ref().map_or(0, |residuals| {
+            bincode::encode_to_vec(residuals, BinConfig::get())
+                .unwrap()
+                .len()
+        });
+        self.result.compressed_data.len() + residuals_size
+    }
+}
 
 /// This is the structure of a compressor frame
-#[derive(Encode, Decode, Debug, Clone)]
+#[derive(Debug, Clone)]
 pub struct CompressorFrame {
     /// The frame size in bytes,
-    frame_size: usize,
-    sample_count: usize,
+    frame_size: u64,
+    sample_count: u64,
     compressor: Compressor,
     /// Output from the compressor
     data: Vec<u8>,
+    /// CRC-32 of the encoded frame, calculated when the frame is closed
+    checksum: u32,
+    /// Summary of the samples, frames from older streams don't have it
+    summary: Option<FrameSummary>,
+    /// Non-finite samples, the compressor got them filled in and they are put back on decompression
+    exceptions: Exceptions,
+    /// What the compressor output misses from the samples, for model compressors that were asked to keep it
+    residuals: Option<Residuals>,
+    /// Samples of the next frame that the compressor output also covers, to be crossfaded with it.
+    /// None for frames written in the layout of streams that aren't lapped.
+    tail: Option<u64>,
+}
+
+// Implementing the Encode manually because the checksum is stored next to the frame, not inside it.
+// The summary, the exceptions and the residuals aren't part of it either, `to_bytes` writes them after the frame so older
+// frames, that end right there, can still be read.
+impl Encode for CompressorFrame {
+    fn encode<__E: ::bincode::enc::Encoder>(
+        &self,
+        encoder: &mut __E,
+    ) -> Result<(), ::bincode::error::EncodeError> {
+        Encode::encode(&self.frame_size, encoder)?;
+        Encode::encode(&self.sample_count, encoder)?;
+        Encode::encode(&self.compressor, encoder)?;
+        Encode::encode(&self.data, encoder)?;
+        Ok(())
+    }
 }
 
+impl Decode for CompressorFrame {
+    fn decode<__D: ::bincode::de::Decoder>(
+        decoder: &mut __D,
+    ) -> Result<Self, ::bincode::error::DecodeError> {
+        Ok(Self {
+            frame_size: Decode::decode(decoder)?,
+            sample_count: Decode::decode(decoder)?,
+            compressor: Decode::decode(decoder)?,
+            data: Decode::decode(decoder)?,
+            checksum: 0,
+            summary: None,
+            exceptions: Exceptions::default(),
+            residuals: None,
+            tail: None,
+        })
+    }
+}
+
+bincode::impl_borrow_decode!(CompressorFrame);
+
 impl CompressorFrame {
     ///  Creates a compressor frame, if a compressor is provided, it forces that compressor, otherwise is selected
     /// by the optimizer
@@ -42,41 +203,319 @@ impl CompressorFrame {
             sample_count: 0,
             compressor: provided_compressor.unwrap_or_default(),
             data: Vec::new(),
+            checksum: 0,
+            summary: None,
+            exceptions: Exceptions::default(),
+            residuals: None,
+            tail: None,
         }
     }
 
     /// Calculates the size of the Frame and "closes it"
-    // TODO this is probably wrong, so we have to use the write stream to dump the bytes written
     pub fn close(&mut self) {
-        let size = size_of_val(&self.sample_count)
-            + size_of_val(&self.compressor)
-            + size_of_val(&self.data)
-            + size_of_val(&self.frame_size);
-        self.frame_size = size;
+        // The size is part of the frame, and a bigger size might need more bytes to encode, loop until it settles
+        let mut size = 0;
+        loop {
+            self.frame_size = size;
+            let encoded_size = self.to_bytes().len() as u64;
+            if encoded_size == size {
+                break;
+            }
+            size = encoded_size;
+        }
+        self.checksum = crc32(&self.to_bytes());
+    }
+
+    /// Number of samples stored in the frame
+    pub fn sample_count(&self) -> usize {
+        self.sample_count as usize
+    }
+
+    /// Summary of the samples of the frame, None for frames read from streams written without summaries
+    pub fn summary(&self) -> Option<FrameSummary> {
+        self.summary
+    }
+
+    /// Checks if the frame holds non-finite samples
+    pub fn has_exceptions(&self) -> bool {
+        !self.exceptions.is_empty()
+    }
+
+    /// Checks if the frame holds residuals on top of its compressor output
+    pub fn has_residuals(&self) -> bool {
+        self.residuals.is_some()
+    }
+
+    /// Number of samples of the next frame that the frame also holds
+    pub fn tail(&self) -> usize {
+        self.tail.unwrap_or(0) as usize
+    }
+
+    /// Makes the last `tail` samples given to the compressor belong to the next frame. They are compressed with the
+    /// frame, so its model runs past its last sample, and crossfaded with the next frame on decompression.
+    pub fn set_tail(&mut self, tail: usize) {
+        self.tail = Some(tail as u64);
+    }
+
+    /// Checks if the frame is written in the layout of lapped streams
+    pub fn is_lapped(&self) -> bool {
+        self.tail.is_some()
+    }
+
+    /// Moves the frame to the layout of lapped streams, without a tail if it had none
+    pub fn set_lapped(&mut self) {
+        if self.tail.is_none() {
+            self.tail = Some(0);
+            self.close();
+        }
+    }
+
+    /// CRC-32 of the frame, as calculated when it was closed or as read from the stream
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    /// Transforms the frame, its summary, its exceptions, its tail and its residuals into bytes, this is what the
+    /// checksum covers. Each block is only written when it isn't empty or when a block follows it, frames holding
+    /// them always have a summary before them. The tail is only written by frames of lapped streams.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        let mut bytes = bincode::encode_to_vec(self, config).unwrap();
+        if let Some(summary) = &self.summary {
+            bytes.extend(bincode::encode_to_vec(summary, config).unwrap());
+            let tail = self
+                .tail
+                .filter(|&tail| tail > 0 || self.residuals.is_some());
+            if !self.exceptions.is_empty() || tail.is_some() || self.residuals.is_some() {
+                bytes.extend(bincode::encode_to_vec(&self.exceptions, config).unwrap());
+            }
+            if let Some(tail) = tail {
+                bytes.extend(bincode::encode_to_vec(tail, config).unwrap());
+            }
+            if let Some(residuals) = &self.residuals {
+                bytes.extend(bincode::encode_to_vec(residuals, config).unwrap());
+            }
+        }
+        bytes
+    }
+
+    /// Generates a frame from its bytes, failing if they don't match the checksum stored for the frame
+    pub fn from_bytes(data: &[u8], checksum: u32) -> Result<Self, Error> {
+        CompressorFrame::decode_blocks(data, checksum, false)
+    }
+
+    /// Generates a frame of a lapped stream from its bytes, failing if they don't match the checksum stored for the
+    /// frame
+    pub fn from_lapped_bytes(data: &[u8], checksum: u32) -> Result<Self, Error> {
+        CompressorFrame::decode_blocks(data, checksum, true)
+    }
+
+    fn decode_blocks(data: &[u8], checksum: u32, lapped: bool) -> Result<Self, Error> {
+        if crc32(data) != checksum {
+            return Err(Error::CorruptData("frame checksum mismatch".to_string()));
+        }
+        let config = BinConfig::get();
+        let (mut frame, mut read): (CompressorFrame, usize) =
+            bincode::decode_from_slice(data, config)?;
+        if read < data.len() {
+            let (summary, summary_size) = bincode::decode_from_slice(&data[read..], config)?;
+            frame.summary = Some(summary);
+            read += summary_size;
+        }
+        if read < data.len() {
+            let (exceptions, exceptions_size) = bincode::decode_from_slice(&data[read..], config)?;
+            frame.exceptions = exceptions;
+            read += exceptions_size;
+        }
+        if lapped {
+            let (tail, tail_size) = if read < data.len() {
+                bincode::decode_from_slice(&data[read..], config)?
+            } else {
+                (0, 0)
+            };
+            frame.tail = Some(tail);
+            read += tail_size;
+        }
+        if read < data.len() {
+            let (residuals, _) = bincode::decode_from_slice(&data[read..], config)?;
+            frame.residuals = Some(residuals);
+        }
+        frame.checksum = checksum;
+        Ok(frame)
+    }
+
+    /// Records the sample count, the summary and the non-finite samples of `data`, and returns the data to feed
+    /// the compressor, with the non-finite samples filled in
+    fn prepare<'a>(&mut self, data: &'a [f64]) -> Cow<'a, [f64]> {
+        let sample_count = data.len().saturating_sub(self.tail());
+        self.sample_count = sample_count as u64;
+        self.summary = Some(FrameSummary::new(&data[..sample_count]));
+        self.exceptions = Exceptions::find(data);
+        if self.exceptions.is_empty() {
+            Cow::Borrowed(data)
+        } else {
+            debug!(
+                "Filling {} runs of non-finite samples",
+                self.exceptions.runs().len()
+            );
+            Cow::Owned(fill_gaps(data))
+        }
     }
 
     /// Compress a data and stores the result in the frame
-    pub fn compress(&mut self, data: &[f64]) {
-        self.sample_count = data.len();
-        self.data = self.compressor.compress(data);
+    pub fn compress(&mut self, data: &[f64]) -> Result<(), Error> {
+        let data = self.prepare(data);
+        self.data = self.compressor.compress(&data)?;
+        Ok(())
     }
 
     /// Compress a data and stores the result in the frame
-    pub fn compress_bounded(&mut self, data: &[f64], max_error: f32) {
-        self.sample_count = data.len();
-        self.data = self.compressor.compress_bounded(data, max_error as f64);
+    pub fn compress_bounded(&mut self, data: &[f64], max_error: f32) -> Result<(), Error> {
+        let data = self.prepare(data);
+        self.data = self.compressor.compress_bounded(&data, max_error as f64)?;
+        Ok(())
+    }
+
+    /// Compress a data with the model of the frame compressor, and stores what the model misses as residuals so
+    /// every sample is within `quantum / 2` of the original one. A quantum of 0 makes it lossless.
+    /// With `Compressor::Auto` every model compressor is tried and the smallest one, residuals included, is kept.
+    pub fn compress_with_residuals(&mut self, data: &[f64], quantum: f64) -> Result<(), Error> {
+        let compressor_list = match self.compressor {
+            Compressor::Auto => vec![
+                Compressor::FFT,
+                Compressor::Dct,
+                Compressor::Polynomial,
+                Compressor::Idw,
+                Compressor::Wavelet,
+                Compressor::Chebyshev,
+            ],
+            compressor if compressor.supports_residuals() => vec![compressor],
+            compressor => return Err(Error::UnsupportedCompressor(compressor)),
+        };
+        let data = self.prepare(data);
+        let candidates = compressor_list
+            .into_iter()
+            .map(|compressor| {
+                let (result, residuals) =
+                    CompressorFrame::model_with_residuals(compressor, &data, quantum)?;
+                Ok(Candidate {
+                    compressor,
+                    result,
+                    residuals: Some(residuals),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let best = candidates.into_iter().min_by_key(Candidate::size).unwrap();
+        self.compressor = best.compressor;
+        self.data = best.result.compressed_data;
+        self.residuals = best.residuals;
+        Ok(())
+    }
+
+    /// Compresses `data` with the default model of `compressor` and codes what it misses as residuals. The result
+    /// holds the compressor output, its size is the size of both.
+    fn model_with_residuals(
+        compressor: Compressor,
+        data: &[f64],
+        quantum: f64,
+    ) -> Result<(CompressorResult, Residuals), Error> {
+        let compressed_data = compressor.compress(data)?;
+        let mut model = compressor.decompress(data.len(), &compressed_data)?;
+        let residuals = Residuals::new(data, &model, quantum);
+        let error = if quantum == 0.0 {
+            0.0
+        } else {
+            residuals.apply(&mut model)?;
+            calculate_error(data, &model)
+        };
+        Ok((CompressorResult::new(compressed_data, error), residuals))
+    }
+
+    /// Runs `compressor` over `data`. On lossless frames the model compressors, that can't get to an error of 0
+    /// on their own, keep their residuals.
+    fn evaluate(compressor: Compressor, data: &[f64], max_error: f32) -> Result<Candidate, Error> {
+        if max_error == 0.0 && compressor.supports_residuals() {
+            let (result, residuals) = CompressorFrame::model_with_residuals(compressor, data, 0.0)?;
+            return Ok(Candidate {
+                compressor,
+                result,
+                residuals: Some(residuals),
+            });
+        }
+        Ok(Candidate {
+            compressor,
+            result: compressor.get_compress_bounded_results(data, max_error as f64)?,
+            residuals: None,
+        })
+    }
+
+    /// Picks the smallest result within the error, if none is within the error, picks the smallest one.
+    fn pick_best(results: Vec<Candidate>, max_error: f32) -> Option<Candidate> {
+        #[allow(
+            clippy::neg_cmp_op_on_partial_ord,
+            reason = "we need to exactly negate `result.error < max_error`, we can't apply de morgans to the expression due to NaN values"
+        )]
+        if results
+            .iter()
+            .all(|candidate| !(candidate.result.error <= max_error as f64))
+        {
+            // To ensure we always have at least one result,
+            // if all results are above the max error just pick the smallest.
+            results.into_iter().min_by_key(Candidate::size)
+        } else {
+            results
+                .into_iter()
+                .filter(|candidate| candidate.result.error <= max_error as f64)
+                .min_by_key(Candidate::size)
+        }
+    }
+
+    /// Keeps the output of `candidate` as the content of the frame
+    fn set_candidate(&mut self, candidate: Candidate) {
+        self.compressor = candidate.compressor;
+        self.data = candidate.result.compressed_data;
+        self.residuals = candidate.residuals;
     }
 
     /// This function tries to detect the best compressor for use and apply it to the data size
-    pub fn compress_best(&mut self, data: &[f64], max_error: f32, compression_speed: usize) {
-        self.sample_count = data.len();
+    pub fn compress_best(
+        &mut self,
+        data: &[f64],
+        max_error: f32,
+        compression_speed: usize,
+    ) -> Result<(), Error> {
+        if data.is_empty() {
+            return Err(Error::EmptyInput);
+        }
+        let data = self.prepare(data);
+        let data = &data[..];
         // Speed factor limits the amount of data that is sampled to calculate the best compressor.
         // We need enough samples to do decent compression, minimum is 128 (2^7)
-        let data_sample = COMPRESSION_SPEED[compression_speed] as usize;
-        // Eligible compressors for use
-        let compressor_list = [Compressor::FFT, Compressor::Polynomial];
+        let data_sample =
+            COMPRESSION_SPEED[compression_speed.min(COMPRESSION_SPEED.len() - 1)] as usize;
         // Do a statistical analysis of the data, let's see if we can pick a compressor out of this.
         let stats = DataStats::new(data);
+        // Eligible compressors for use, lossless compression only makes sense if no error is allowed
+        let mut compressor_list = vec![Compressor::FFT, Compressor::Dct, Compressor::Polynomial];
+        if max_error == 0.0 {
+            compressor_list.push(Compressor::Gorilla);
+        } else {
+            compressor_list.push(Compressor::Pla);
+            compressor_list.push(Compressor::Chebyshev);
+            compressor_list.push(Compressor::Seasonal);
+        }
+        // Integer series pack very well as deltas, even when some error is allowed
+        if !stats.fractional && fits_delta(data) {
+            compressor_list.push(Compressor::Delta);
+        }
+        // Counters never go backwards when stored as counters, as long as their values scale to exact integers
+        if is_counter(data) && counter_decimals(data).is_some() {
+            compressor_list.push(Compressor::Counter);
+        }
+        // Step functions, holding a value for a long time before moving to the next one
+        if run_count(data) <= data.len() / MAX_RUNS_RATIO {
+            compressor_list.push(Compressor::Rle);
+        }
         // Checking the statistical analysis and chose, if possible, a compressor
         // If the data is constant, well, constant frame
         if stats.min == stats.max {
@@ -84,76 +523,73 @@ impl CompressorFrame {
             // Now do the full data compression
             self.data = self
                 .compressor
-                .get_compress_bounded_results(data, max_error as f64)
+                .get_compress_bounded_results(data, max_error as f64)?
                 .compressed_data;
-        } else if self.sample_count >= data_sample {
+        } else if data.len() >= data_sample {
             // Any technique determine the best compressor seems to be slower than this one
             // Sample the dataset for a fast compressor run
             // Pick the best compression
             // Compress the full dataset that way
-            let (_smallest_result, chosen_compressor) = compressor_list
+            let sample_results = compressor_list
                 .iter()
                 .map(|compressor| {
-                    (
-                        compressor
-                            .get_compress_bounded_results(&data[0..data_sample], max_error as f64),
-                        compressor,
-                    )
+                    CompressorFrame::evaluate(*compressor, &data[0..data_sample], max_error)
                 })
-                .filter(|(result, _)| result.error <= max_error as f64)
-                .min_by_key(|x| x.0.compressed_data.len())
-                .unwrap();
-            self.compressor = *chosen_compressor;
+                .collect::<Result<Vec<_>, Error>>()?;
+            let chosen = CompressorFrame::pick_best(sample_results, max_error)
+                .ok_or(Error::UnsupportedCompressor(Compressor::Auto))?;
             // Now do the full data compression
-            self.data = self
-                .compressor
-                .get_compress_bounded_results(data, max_error as f64)
-                .compressed_data;
+            self.set_candidate(CompressorFrame::evaluate(
+                chosen.compressor,
+                data,
+                max_error,
+            )?);
         } else {
             // Run all the eligible compressors and choose smallest
-            let compressor_results: Vec<_> = compressor_list
+            let compressor_results = compressor_list
                 .iter()
-                .map(|compressor| {
-                    (
-                        compressor.get_compress_bounded_results(data, max_error as f64),
-                        *compressor,
-                    )
-                })
-                .collect();
-
-            #[allow(
-                clippy::neg_cmp_op_on_partial_ord,
-                reason = "we need to exactly negate `result.error < max_error`, we can't apply de morgans to the expression due to NaN values"
-            )]
-            let best_compressor = if compressor_results
-                .iter()
-                .all(|(result, _)| !(result.error <= max_error as f64))
-            {
-                // To ensure we always have at least one result,
-                // if all results are above the max error just pick the smallest.
-                compressor_results
-                    .into_iter()
-                    .min_by_key(|x| x.0.compressed_data.len())
-            } else {
-                compressor_results
-                    .into_iter()
-                    .filter(|(result, _)| result.error <= max_error as f64)
-                    .min_by_key(|x| x.0.compressed_data.len())
-            };
-
-            let (result, compressor) = best_compressor.unwrap();
-            self.data = result.compressed_data;
-            self.compressor = compressor;
+                .map(|compressor| CompressorFrame::evaluate(*compressor, data, max_error))
+                .collect::<Result<Vec<_>, Error>>()?;
+            let best = CompressorFrame::pick_best(compressor_results, max_error)
+                .ok_or(Error::UnsupportedCompressor(Compressor::Auto))?;
+            self.set_candidate(best);
         }
         debug!("Auto Compressor Selection: {:?}", self.compressor);
+        Ok(())
     }
 
     /// Decompresses a frame and returns the resulting data array
-    pub fn decompress(&self) -> Vec<f64> {
+    pub fn decompress(&self) -> Result<Vec<f64>, Error> {
+        Ok(self.decompress_lapped(&[])?.0)
+    }
+
+    /// Decompresses a frame, crossfading its first samples with `previous_tail`, the tail of the frame before it.
+    /// Returns the samples of the frame and its own tail, for the next frame.
+    pub fn decompress_lapped(&self, previous_tail: &[f64]) -> Result<(Vec<f64>, Vec<f64>), Error> {
         debug!(
-            "Decompressing Frame. Size: {}, Samples: {}",
-            self.frame_size, self.sample_count
+            "Decompressing Frame. Size: {}, Samples: {}, Tail: {}",
+            self.frame_size,
+            self.sample_count,
+            self.tail()
         );
-        self.compressor.decompress(self.sample_count, &self.data)
+        let expected = self
+            .sample_count()
+            .checked_add(self.tail())
+            .ok_or(Error::CorruptData("frame tail is too long".to_string()))?;
+        let mut data = self.compressor.decompress(expected, &self.data)?;
+        if data.len() != expected {
+            return Err(Error::CorruptData(format!(
+                "frame decompressed into {} samples, {} expected",
+                data.len(),
+                expected
+            )));
+        }
+        if let Some(residuals) = &self.residuals {
+            residuals.apply(&mut data)?;
+        }
+        self.exceptions.restore(&mut data)?;
+        let tail = data.split_off(self.sample_count());
+        crossfade(&mut data, previous_tail);
+        Ok((data, tail))
     }
 }
@@ -0,0 +1,248 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::compressor::counter::{find_decimals, MAX_EXACT_INT};
+use crate::error::Error;
+use crate::utils::bits::{unzigzag, zigzag, BitReader, BitWriter};
+use crate::utils::DECIMAL_PRECISION;
+use bincode::{Decode, Encode};
+
+/// Residuals are coded in blocks of this size, each with its own Rice parameter
+const BLOCK_SIZE: usize = 128;
+/// Bits needed to store a Rice parameter (0 to 63)
+const PARAMETER_BITS: u32 = 6;
+/// Quotients this big are not written in unary, the value follows with all its bits
+const ESCAPE: u64 = 24;
+
+/// What each residual counts
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    /// Multiples of the quantum
+    Quantum(f64),
+    /// Units of the last decimal, for samples with at most this many decimals
+    Decimals(u8),
+    /// Differences between the bits of the values, for samples with any number of decimals
+    Bits,
+}
+
+/// Difference between the samples of a frame and what its model compressor (FFT, polynomial, ...) gives back.
+///
+/// Each difference is quantized to a multiple of a quantum, so every sample ends up within half a quantum of the
+/// original one. With a quantum of 0 the samples are restored exactly, differences are counted in units of the
+/// last decimal of the samples, or between the bits of the values if they have too many decimals for that.
+/// Differences are zigzag encoded and Rice coded, a good model leaves mostly small differences that take a few
+/// bits each. They are taken against the decompressed model, so both ends apply them to the same values.
+#[derive(Encode, Decode, Debug, Clone, PartialEq)]
+pub struct Residuals {
+    step: Step,
+    data: Vec<u8>,
+}
+
+/// Maps the bits of a f64 to an integer that grows with the value, so close values are close integers
+fn to_ordered(value: f64) -> i64 {
+    let bits = value.to_bits() as i64;
+    if bits < 0 {
+        bits ^ i64::MAX
+    } else {
+        bits
+    }
+}
+
+fn from_ordered(ordered: i64) -> f64 {
+    let bits = if ordered < 0 {
+        ordered ^ i64::MAX
+    } else {
+        ordered
+    };
+    f64::from_bits(bits as u64)
+}
+
+/// Bits taken by `value` with the Rice parameter `k`
+fn rice_size(value: u64, k: u32) -> u64 {
+    let quotient = value >> k;
+    if quotient < ESCAPE {
+        quotient + 1 + k as u64
+    } else {
+        ESCAPE + 64
+    }
+}
+
+impl Residuals {
+    /// Residuals taking `model` back to `original`, within `quantum / 2` of it or exactly with a quantum of 0
+    pub fn new(original: &[f64], model: &[f64], quantum: f64) -> Self {
+        let step = if quantum != 0.0 {
+            Step::Quantum(quantum)
+        } else {
+            let decimals = find_decimals(original);
+            let scale = 10f64.powi(decimals as i32);
+            // Scaled integers have no negative zero, only the bits keep its sign
+            if decimals < DECIMAL_PRECISION
+                && model.iter().all(|m| (m * scale).abs() < MAX_EXACT_INT)
+                && !original.iter().any(|v| *v == 0.0 && v.is_sign_negative())
+            {
+                Step::Decimals(decimals as u8)
+            } else {
+                Step::Bits
+            }
+        };
+        let values: Vec<u64> = original
+            .iter()
+            .zip(model.iter())
+            .map(|(&original, &model)| {
+                zigzag(match step {
+                    Step::Quantum(quantum) => ((original - model) / quantum).round() as i64,
+                    Step::Decimals(decimals) => {
+                        let scale = 10f64.powi(decimals as i32);
+                        (original * scale).round() as i64 - (model * scale).round() as i64
+                    }
+                    Step::Bits => to_ordered(original).wrapping_sub(to_ordered(model)),
+                })
+            })
+            .collect();
+        let mut writer = BitWriter::new();
+        for block in values.chunks(BLOCK_SIZE) {
+            let k = (0..64)
+                .min_by_key(|&k| block.iter().map(|&v| rice_size(v, k)).sum::<u64>())
+                .unwrap();
+            writer.write_bits(k as u64, PARAMETER_BITS);
+            for &value in block {
+                let quotient = value >> k;
+                if quotient < ESCAPE {
+                    for _ in 0..quotient {
+                        writer.write_bit(true);
+                    }
+                    writer.write_bit(false);
+                    writer.write_bits(value, k);
+                } else {
+                    writer.write_bits((1 << ESCAPE) - 1, ESCAPE as u32);
+                    writer.write_bits(value, 64);
+                }
+            }
+        }
+        Residuals {
+            step,
+            data: writer.finish(),
+        }
+    }
+
+    /// Largest distance between a restored sample and the original one
+    pub fn max_error(&self) -> f64 {
+        match self.step {
+            Step::Quantum(quantum) => quantum / 2.0,
+            Step::Decimals(_) | Step::Bits => 0.0,
+        }
+    }
+
+    /// Adds the residuals to the samples generated by the model
+    pub fn apply(&self, model: &mut [f64]) -> Result<(), Error> {
+        match self.step {
+            Step::Quantum(quantum) if quantum.is_nan() || quantum <= 0.0 => {
+                return Err(Error::CorruptData(format!(
+                    "residuals with a quantum of {}",
+                    quantum
+                )))
+            }
+            Step::Decimals(decimals) if decimals as u32 >= DECIMAL_PRECISION => {
+                return Err(Error::CorruptData(format!(
+                    "residuals with {} decimals",
+                    decimals
+                )))
+            }
+            _ => {}
+        }
+        let mut reader = BitReader::new(&self.data);
+        for block in model.chunks_mut(BLOCK_SIZE) {
+            let k = reader.read_bits(PARAMETER_BITS)? as u32;
+            for sample in block.iter_mut() {
+                let mut quotient = 0;
+                while quotient < ESCAPE && reader.read_bit()? {
+                    quotient += 1;
+                }
+                let value = if quotient < ESCAPE {
+                    quotient << k | reader.read_bits(k)?
+                } else {
+                    reader.read_bits(64)?
+                };
+                let residual = unzigzag(value);
+                *sample = match self.step {
+                    Step::Quantum(quantum) => *sample + residual as f64 * quantum,
+                    Step::Decimals(decimals) => {
+                        let scale = 10f64.powi(decimals as i32);
+                        ((*sample * scale).round() as i64).wrapping_add(residual) as f64 / scale
+                    }
+                    Step::Bits => from_ordered(to_ordered(*sample).wrapping_add(residual)),
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lossless_residuals() {
+        let bits = |d: &[f64]| d.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        // Two decimals, the model is off by a few hundredths
+        let original: Vec<f64> = (0..1000)
+            .map(|i| ((100.0 + (i as f64 / 20.0).sin() * 3.3) * 100.0).round() / 100.0)
+            .collect();
+        let mut model: Vec<f64> = (0..1000)
+            .map(|i| 100.0 + (i as f64 / 20.0).sin() * 3.3 + ((i % 5) as f64 - 2.0) * 0.01)
+            .collect();
+        let residuals = Residuals::new(&original, &model, 0.0);
+        assert_eq!(residuals.step, Step::Decimals(2));
+        assert!(residuals.data.len() < original.len() / 2);
+        residuals.apply(&mut model).unwrap();
+        assert_eq!(bits(&model), bits(&original));
+        // A negative zero would come back as a positive one out of the decimals
+        let original = [1.5, -0.0, 2.25];
+        let mut model = [1.0, 0.0, 2.0];
+        let residuals = Residuals::new(&original, &model, 0.0);
+        assert_eq!(residuals.step, Step::Bits);
+        residuals.apply(&mut model).unwrap();
+        assert_eq!(bits(&model), bits(&original));
+        // Too many decimals, the bits of the values are used
+        let original = [-0.0, 1e300, -1e-300, f64::MAX, 0.1234567];
+        let mut model = [0.0, -1e300, 1e-300, 0.0, 0.1234];
+        let residuals = Residuals::new(&original, &model, 0.0);
+        assert_eq!(residuals.step, Step::Bits);
+        residuals.apply(&mut model).unwrap();
+        assert_eq!(bits(&model), bits(&original));
+    }
+
+    #[test]
+    fn test_quantized_residuals() {
+        let original: Vec<f64> = (0..500)
+            .map(|i| ((i * 7919) % 1013) as f64 * 0.37)
+            .collect();
+        let mut model = vec![180.0; 500];
+        let residuals = Residuals::new(&original, &model, 0.5);
+        assert_eq!(residuals.max_error(), 0.25);
+        residuals.apply(&mut model).unwrap();
+        for (original, restored) in original.iter().zip(model.iter()) {
+            assert!((original - restored).abs() <= 0.25 + 1e-9);
+        }
+        // Missing residuals
+        let mut short = vec![0.0; 1000];
+        assert!(matches!(
+            residuals.apply(&mut short),
+            Err(Error::TruncatedInput)
+        ));
+    }
+}
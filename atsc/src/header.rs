@@ -14,25 +14,251 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use crate::error::Error;
 use bincode::{Decode, Encode};
 
-#[derive(Encode, Decode, Debug, Clone)]
+/// Every BRRO stream starts with this
+pub const MAGIC: [u8; 4] = *b"BRRO";
+/// Version of the container layout written by this release. Bump it every time the layout changes in a way that
+/// older readers can't handle, and keep the decoding path for the previous versions around.
+pub const FORMAT_VERSION: u16 = 1;
+/// The original layout. It had no version, the slot now used by it held an `i16` frame count that was never
+/// incremented, so it always encodes as 0.
+pub const LEGACY_FORMAT_VERSION: u16 = 0;
+/// Every frame carries a CRC-32 of its content, and the stream ends with a checksum of the whole stream
+pub const FEATURE_CHECKSUMS: u32 = 1;
+/// The stream ends with an index of its frames, see `FrameIndex`
+pub const FEATURE_INDEX: u32 = 1 << 1;
+/// A metadata block follows the header, see `Metadata`
+pub const FEATURE_METADATA: u32 = 1 << 2;
+/// Some frames hold non-finite samples, older releases would decompress them as the values they were filled with
+pub const FEATURE_EXCEPTIONS: u32 = 1 << 3;
+/// Some frames hold residuals on top of their compressor output, older releases would decompress the model alone
+pub const FEATURE_RESIDUALS: u32 = 1 << 4;
+/// Frames overlap the next frame and are crossfaded with it, their tail is written between the exceptions and the
+/// residuals
+pub const FEATURE_LAPPED: u32 = 1 << 5;
+/// Feature flags known by this release. A stream using any flag outside this set was written by a newer release
+/// and can't be read.
+pub const KNOWN_FEATURES: u32 = FEATURE_CHECKSUMS
+    | FEATURE_INDEX
+    | FEATURE_METADATA
+    | FEATURE_EXCEPTIONS
+    | FEATURE_RESIDUALS
+    | FEATURE_LAPPED;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct CompressorHeader {
     initial_segment: [u8; 4],
-    // We should go unsigned
-    frame_count: i16,
+    /// Version of the layout the stream was written with
+    version: u16,
+    /// Bitfield of optional features used by the stream
+    features: u32,
+    frame_count: u64,
+    /// CRC-32 of the metadata checksum and the frames checksums, stored as 4 fixed bytes so it can be updated in
+    /// place
+    stream_checksum: u32,
+}
+
+impl Encode for CompressorHeader {
+    fn encode<__E: ::bincode::enc::Encoder>(
+        &self,
+        encoder: &mut __E,
+    ) -> Result<(), ::bincode::error::EncodeError> {
+        Encode::encode(&self.initial_segment, encoder)?;
+        Encode::encode(&self.version, encoder)?;
+        if self.version == LEGACY_FORMAT_VERSION {
+            return Ok(());
+        }
+        Encode::encode(&self.features, encoder)?;
+        Encode::encode(&self.frame_count, encoder)?;
+        Encode::encode(&self.stream_checksum.to_le_bytes(), encoder)?;
+        Ok(())
+    }
+}
+
+impl Decode for CompressorHeader {
+    fn decode<__D: ::bincode::de::Decoder>(
+        decoder: &mut __D,
+    ) -> Result<Self, ::bincode::error::DecodeError> {
+        let initial_segment: [u8; 4] = Decode::decode(decoder)?;
+        if initial_segment != MAGIC {
+            return Err(::bincode::error::DecodeError::Other(
+                "Not a BRRO stream, magic bytes don't match",
+            ));
+        }
+        let version: u16 = Decode::decode(decoder)?;
+        if version == LEGACY_FORMAT_VERSION {
+            // The version byte was the (always zero) frame count, nothing else to read
+            return Ok(Self {
+                initial_segment,
+                version,
+                features: 0,
+                frame_count: 0,
+                stream_checksum: 0,
+            });
+        }
+        if version > FORMAT_VERSION {
+            // No way to know how the rest is laid out, `check` refuses this header
+            return Ok(Self {
+                initial_segment,
+                version,
+                features: 0,
+                frame_count: 0,
+                stream_checksum: 0,
+            });
+        }
+        let features: u32 = Decode::decode(decoder)?;
+        let frame_count: u64 = Decode::decode(decoder)?;
+        let stream_checksum = u32::from_le_bytes(Decode::decode(decoder)?);
+        Ok(Self {
+            initial_segment,
+            version,
+            features,
+            frame_count,
+            stream_checksum,
+        })
+    }
 }
 
+bincode::impl_borrow_decode!(CompressorHeader);
+
 impl CompressorHeader {
     pub fn new() -> Self {
         CompressorHeader {
-            initial_segment: *b"BRRO",
+            initial_segment: MAGIC,
+            version: FORMAT_VERSION,
+            features: FEATURE_CHECKSUMS | FEATURE_INDEX,
             // We have to limit the bytes of the header
             frame_count: 0,
+            stream_checksum: 0,
         }
     }
 
     pub fn add_frame(&mut self) {
         self.frame_count += 1;
     }
+
+    /// Sets the frame count of a header that doesn't store it, like the legacy one
+    pub fn set_frame_count(&mut self, frame_count: usize) {
+        self.frame_count = frame_count as u64;
+    }
+
+    /// Moves a header read from an older stream to the current layout, so the stream is written back in it
+    pub fn upgrade(&mut self, frame_count: usize) {
+        self.version = FORMAT_VERSION;
+        self.features |= FEATURE_CHECKSUMS | FEATURE_INDEX;
+        self.frame_count = frame_count as u64;
+    }
+
+    /// Turns a feature flag on or off
+    pub fn set_feature(&mut self, feature: u32, enabled: bool) {
+        if enabled {
+            self.features |= feature;
+        } else {
+            self.features &= !feature;
+        }
+    }
+
+    /// Checks if this release is able to read a stream with this header
+    pub fn check(&self) -> Result<(), Error> {
+        if self.version > FORMAT_VERSION {
+            return Err(Error::UnsupportedFormat(format!(
+                "stream format version {} is newer than the supported version {}, please upgrade atsc",
+                self.version, FORMAT_VERSION
+            )));
+        }
+        if self.features & !KNOWN_FEATURES != 0 {
+            return Err(Error::UnsupportedFormat(format!(
+                "stream uses unknown features ({:#x}), please upgrade atsc",
+                self.features & !KNOWN_FEATURES
+            )));
+        }
+        Ok(())
+    }
+
+    /// Layout version the stream was written with
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn stream_checksum(&self) -> u32 {
+        self.stream_checksum
+    }
+
+    pub fn set_stream_checksum(&mut self, checksum: u32) {
+        self.stream_checksum = checksum;
+    }
+
+    /// Checks if the stream was written with the given feature flag(s)
+    pub fn has_features(&self, features: u32) -> bool {
+        self.features & features == features
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_count as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::BinConfig;
+
+    #[test]
+    fn test_header_roundtrip() {
+        let mut header = CompressorHeader::new();
+        header.add_frame();
+        header.set_stream_checksum(0x0403_0201);
+        let bytes = bincode::encode_to_vec(&header, BinConfig::get()).unwrap();
+        assert_eq!(bytes, [66, 82, 82, 79, 1, 3, 1, 1, 2, 3, 4]);
+        let (decoded, _): (CompressorHeader, usize) =
+            bincode::decode_from_slice(&bytes, BinConfig::get()).unwrap();
+        assert_eq!(decoded, header);
+    }
+
+    #[test]
+    fn test_legacy_header() {
+        let (header, read): (CompressorHeader, usize) =
+            bincode::decode_from_slice(&[66, 82, 82, 79, 0, 1], BinConfig::get()).unwrap();
+        assert_eq!(header.version(), LEGACY_FORMAT_VERSION);
+        assert_eq!(read, 5);
+    }
+
+    #[test]
+    fn test_newer_version_rejected() {
+        let (header, _): (CompressorHeader, usize) =
+            bincode::decode_from_slice(&[66, 82, 82, 79, 200, 0, 0], BinConfig::get()).unwrap();
+        assert!(matches!(header.check(), Err(Error::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_unknown_features_rejected() {
+        let (header, _): (CompressorHeader, usize) =
+            bincode::decode_from_slice(&[66, 82, 82, 79, 1, 128, 0, 0, 0, 0, 0], BinConfig::get())
+                .unwrap();
+        assert!(matches!(header.check(), Err(Error::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_bad_magic_rejected() {
+        let result: std::result::Result<(CompressorHeader, usize), _> =
+            bincode::decode_from_slice(&[66, 82, 82, 80, 1, 0, 0], BinConfig::get());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_large_frame_count() {
+        let mut header = CompressorHeader::new();
+        header.upgrade(300_000);
+        let bytes = bincode::encode_to_vec(&header, BinConfig::get()).unwrap();
+        // Varint: 252 followed by the count as a little endian u32
+        assert_eq!(
+            bytes,
+            [66, 82, 82, 79, 1, 3, 252, 224, 147, 4, 0, 0, 0, 0, 0]
+        );
+        let (decoded, _): (CompressorHeader, usize) =
+            bincode::decode_from_slice(&bytes, BinConfig::get()).unwrap();
+        assert_eq!(decoded.frame_count(), 300_000);
+    }
 }
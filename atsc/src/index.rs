@@ -0,0 +1,162 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::compressor::BinConfig;
+use crate::error::Error;
+use crate::utils::checksum::crc32;
+use bincode::{Decode, Encode};
+
+/// Marks the end of a stream that carries a frame index
+pub const INDEX_MAGIC: [u8; 4] = *b"BRIX";
+/// The trailer is the index checksum (4 bytes), the index size (4 bytes) and the magic (4 bytes)
+pub const TRAILER_SIZE: usize = 12;
+
+/// Location of a frame in a serialized stream, and of its samples in the decompressed output
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq)]
+pub struct FrameIndexEntry {
+    /// Byte offset of the frame from the start of the stream
+    pub offset: u64,
+    /// Bytes used by the frame, including its length and checksum
+    pub size: u64,
+    /// Position of the first sample of the frame in the decompressed output
+    pub sample_offset: u64,
+    pub sample_count: u64,
+}
+
+impl FrameIndexEntry {
+    /// Checks if the frame holds any sample in [start, end)
+    pub fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.sample_offset < end && self.sample_offset + self.sample_count > start
+    }
+}
+
+/// Index of the frames of a stream, written at the end of it so a reader can seek straight to the frames
+/// holding a range of samples.
+#[derive(Encode, Decode, Debug, Clone, Default, PartialEq)]
+pub struct FrameIndex {
+    entries: Vec<FrameIndexEntry>,
+}
+
+impl FrameIndex {
+    pub fn new() -> Self {
+        FrameIndex {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds the next frame of the stream to the index
+    pub fn push(&mut self, offset: u64, size: u64, sample_count: u64) {
+        let sample_offset = self.sample_count();
+        self.entries.push(FrameIndexEntry {
+            offset,
+            size,
+            sample_offset,
+            sample_count,
+        });
+    }
+
+    pub fn entries(&self) -> &[FrameIndexEntry] {
+        &self.entries
+    }
+
+    /// Total number of samples in the indexed frames
+    pub fn sample_count(&self) -> u64 {
+        self.entries
+            .last()
+            .map(|e| e.sample_offset + e.sample_count)
+            .unwrap_or(0)
+    }
+
+    /// Returns the entries of the frames holding any of the `len` samples starting at `start`
+    pub fn frames_in_range(&self, start: u64, len: u64) -> Vec<FrameIndexEntry> {
+        let end = start.saturating_add(len);
+        self.entries
+            .iter()
+            .filter(|e| e.overlaps(start, end))
+            .copied()
+            .collect()
+    }
+
+    /// Transforms the index into bytes followed by the trailer, to be written at the end of a stream
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        let mut out = bincode::encode_to_vec(self, config).unwrap();
+        let checksum = crc32(&out);
+        let size = out.len() as u32;
+        out.extend(checksum.to_le_bytes());
+        out.extend(size.to_le_bytes());
+        out.extend(INDEX_MAGIC);
+        out
+    }
+
+    /// Reads the trailer at the end of a stream, returning the checksum and size of the index before it.
+    /// None if the stream doesn't end with an index trailer.
+    pub fn read_trailer(trailer: &[u8]) -> Option<(u32, usize)> {
+        if trailer.len() != TRAILER_SIZE || trailer[8..] != INDEX_MAGIC {
+            return None;
+        }
+        let checksum = u32::from_le_bytes(trailer[0..4].try_into().unwrap());
+        let size = u32::from_le_bytes(trailer[4..8].try_into().unwrap());
+        Some((checksum, size as usize))
+    }
+
+    /// Generates the index from its bytes, failing if they don't match the checksum stored in the trailer
+    pub fn from_bytes(data: &[u8], checksum: u32) -> Result<Self, Error> {
+        if crc32(data) != checksum {
+            return Err(Error::CorruptData(
+                "frame index checksum mismatch".to_string(),
+            ));
+        }
+        let config = BinConfig::get();
+        let (index, _) = bincode::decode_from_slice(data, config)?;
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_in_range() {
+        let mut index = FrameIndex::new();
+        index.push(10, 20, 100);
+        index.push(30, 20, 100);
+        index.push(50, 20, 50);
+        assert_eq!(index.sample_count(), 250);
+        assert_eq!(index.frames_in_range(0, 10).len(), 1);
+        assert_eq!(index.frames_in_range(99, 2).len(), 2);
+        assert_eq!(index.frames_in_range(100, 100)[0].offset, 30);
+        assert_eq!(index.frames_in_range(240, 1000).len(), 1);
+        assert!(index.frames_in_range(250, 10).is_empty());
+    }
+
+    #[test]
+    fn test_index_roundtrip() {
+        let mut index = FrameIndex::new();
+        index.push(10, 20, 100);
+        index.push(30, 20, 100);
+        let bytes = index.to_bytes();
+        let (checksum, size) =
+            FrameIndex::read_trailer(&bytes[bytes.len() - TRAILER_SIZE..]).unwrap();
+        assert_eq!(size, bytes.len() - TRAILER_SIZE);
+        assert_eq!(
+            FrameIndex::from_bytes(&bytes[..size], checksum).unwrap(),
+            index
+        );
+        assert!(FrameIndex::from_bytes(&bytes[1..size], checksum).is_err());
+    }
+}
@@ -20,10 +20,16 @@ limitations under the License.
 extern crate core;
 
 pub mod compressor;
+pub mod container;
 pub mod data;
+pub mod error;
 pub mod frame;
 pub mod header;
+pub mod index;
+pub mod metadata;
 pub mod utils;
 
 pub mod csv;
 pub mod optimizer;
+
+pub use error::{Error, Result};
@@ -15,14 +15,16 @@ limitations under the License.
 */
 
 use atsc::compressor::Compressor;
+use atsc::container::SeriesContainer;
 use atsc::csv::{read_samples, read_samples_with_headers};
 use atsc::data::CompressedStream;
+use atsc::metadata::Metadata;
 use atsc::optimizer::OptimizerPlan;
 use atsc::utils::readers::bro_reader;
 use clap::{arg, command, Parser};
 use log::{debug, error};
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use wavbrro::wavbrro::WavBrro;
 
 /// Processes the given input based on the provided arguments.
@@ -69,12 +71,54 @@ fn process_directory(arguments: &Args) -> Result<(), Box<dyn Error>> {
 
 fn process_single_file(mut file_path: PathBuf, arguments: &Args) -> Result<(), Box<dyn Error>> {
     debug!("Processing single file...");
-    if arguments.uncompress {
+    if arguments.verify {
+        if let Some(vec) = bro_reader::read_file(&file_path)? {
+            let report = CompressedStream::verify(&vec)?;
+            if !report.is_ok() {
+                return Err(format!(
+                    "Corrupted stream, frames: {:?}, stream checksum ok: {}",
+                    report.corrupt_frames, report.stream_checksum_ok
+                )
+                .into());
+            }
+            println!("{}: {} frames OK", file_path.display(), report.frame_count);
+        }
+    } else if arguments.metadata {
+        if bro_reader::is_bro_file(&file_path)? {
+            let mut file = std::fs::File::open(&file_path)?;
+            match CompressedStream::read_metadata(&mut file)? {
+                Some(metadata) => {
+                    println!("{}:", file_path.display());
+                    for (key, value) in metadata.entries() {
+                        println!("  {}={}", key, value);
+                    }
+                }
+                None => println!("{}: no metadata", file_path.display()),
+            }
+        }
+    } else if arguments.list {
+        let mut file = std::fs::File::open(&file_path)?;
+        for entry in SeriesContainer::read_directory(&mut file)? {
+            println!(
+                "{}\t{} frames\t{} samples\t{} bytes",
+                entry.name, entry.frame_count, entry.sample_count, entry.size
+            );
+        }
+    } else if let Some(name) = &arguments.extract {
+        let mut file = std::fs::File::open(&file_path)?;
+        let stream = SeriesContainer::read_series(&mut file, name)?;
+        file_path.set_file_name(format!("{}.bro", series_file_stem(name)));
+        std::fs::write(file_path, stream.to_bytes())?;
+    } else if arguments.add.is_some() && bro_reader::is_bro_file(&file_path)? {
+        // Already compressed, added as is
+        let stream = CompressedStream::from_bytes(&std::fs::read(&file_path)?)?;
+        write_compressed(file_path, stream, arguments)?;
+    } else if arguments.uncompress {
         //read
         if let Some(vec) = bro_reader::read_file(&file_path)? {
             let arr: &[u8] = &vec;
             //decompress
-            let decompressed_data = decompress_data(arr);
+            let decompressed_data = decompress_data(arr)?;
             if arguments.verbose {
                 println!("Output={:?}", decompressed_data);
             }
@@ -104,11 +148,10 @@ fn process_single_file(mut file_path: PathBuf, arguments: &Args) -> Result<(), B
         }
 
         // Compress
-        let compressed_data = compress_data(&data, arguments);
+        let compressed_data = compress_data(&data, &file_path, arguments)?;
 
         // Write
-        file_path.set_extension("bro");
-        std::fs::write(file_path, compressed_data)?;
+        write_compressed(file_path, compressed_data, arguments)?;
     } else {
         // Read an WavBRRO file and compress it
         let data = WavBrro::from_file(&file_path)?;
@@ -117,22 +160,26 @@ fn process_single_file(mut file_path: PathBuf, arguments: &Args) -> Result<(), B
         }
 
         //compress
-        let compressed_data = compress_data(&data, arguments);
+        let compressed_data = compress_data(&data, &file_path, arguments)?;
 
         //write
-        file_path.set_extension("bro");
-        std::fs::write(file_path, compressed_data)?;
+        write_compressed(file_path, compressed_data, arguments)?;
     }
     Ok(())
 }
 
 /// Compresses the data based on the provided tag and arguments.
-fn compress_data(vec: &[f64], arguments: &Args) -> Vec<u8> {
+fn compress_data(
+    vec: &[f64],
+    source: &Path,
+    arguments: &Args,
+) -> Result<CompressedStream, Box<dyn Error>> {
     debug!("Compressing data!");
     //let optimizer_results = optimizer::process_data(vec, tag);
     // Create Optimization Plan and Stream for the data.
     let mut op = OptimizerPlan::plan(vec);
     let mut cs = CompressedStream::new();
+    cs.set_metadata(build_metadata(source, arguments)?);
     // Assign the compressor if it was selected
     match arguments.compressor {
         CompressorType::Noop => op.set_compressor(Compressor::Noop),
@@ -140,32 +187,148 @@ fn compress_data(vec: &[f64], arguments: &Args) -> Vec<u8> {
         CompressorType::Fft => op.set_compressor(Compressor::FFT),
         CompressorType::Polynomial => op.set_compressor(Compressor::Polynomial),
         CompressorType::Idw => op.set_compressor(Compressor::Idw),
+        CompressorType::Gorilla => op.set_compressor(Compressor::Gorilla),
+        CompressorType::Delta => op.set_compressor(Compressor::Delta),
+        CompressorType::Counter => op.set_compressor(Compressor::Counter),
+        CompressorType::Rle => op.set_compressor(Compressor::Rle),
+        CompressorType::Pla => op.set_compressor(Compressor::Pla),
+        CompressorType::Dct => op.set_compressor(Compressor::Dct),
+        CompressorType::Wavelet => op.set_compressor(Compressor::Wavelet),
+        CompressorType::Chebyshev => op.set_compressor(Compressor::Chebyshev),
+        CompressorType::Seasonal => op.set_compressor(Compressor::Seasonal),
         CompressorType::Auto => op.set_compressor(Compressor::Auto),
     }
-    for (cpr, data) in op.get_execution().into_iter() {
-        debug!("Chunk size: {}", data.len());
-        // If compressor is a losseless one, compress with the error defined, or default
-        match arguments.compressor {
-            CompressorType::Fft
+    if let Some(quantum) = arguments.residual_quantum {
+        if quantum < 0.0 || !quantum.is_finite() {
+            return Err(format!("Invalid residual quantum: {}", quantum).into());
+        }
+    }
+    let lossy = matches!(
+        arguments.compressor,
+        CompressorType::Fft
             | CompressorType::Polynomial
             | CompressorType::Idw
-            | CompressorType::Auto => cs.compress_chunk_bounded_with(
+            | CompressorType::Pla
+            | CompressorType::Dct
+            | CompressorType::Wavelet
+            | CompressorType::Chebyshev
+            | CompressorType::Seasonal
+            | CompressorType::Auto
+    );
+    // Residuals already bound every sample, and lossless frames have no steps to smooth
+    let overlap = if lossy && arguments.residual_quantum.is_none() {
+        arguments.overlap
+    } else {
+        0
+    };
+    for (cpr, data, tail) in op.get_lapped_execution(overlap).into_iter() {
+        debug!("Chunk size: {}", data.len());
+        if let Some(quantum) = arguments.residual_quantum {
+            cs.compress_chunk_with_residuals(data, cpr.to_owned(), quantum)?;
+            continue;
+        }
+        // If compressor is a losseless one, compress with the error defined, or default
+        if lossy {
+            cs.compress_chunk_lapped(
                 data,
+                tail,
                 cpr.to_owned(),
                 arguments.error as f32 / 100.0,
                 arguments.compression_selection_sample_level as usize,
-            ),
-            _ => cs.compress_chunk_with(data, cpr.to_owned()),
+            )?;
+        } else {
+            cs.compress_chunk_with(data, cpr.to_owned())?;
+        }
+    }
+    Ok(cs)
+}
+
+/// Writes a compressed stream next to its source file, appends it to the one already there, or adds it to a series
+/// container, as requested
+fn write_compressed(
+    mut file_path: PathBuf,
+    cs: CompressedStream,
+    arguments: &Args,
+) -> Result<(), Box<dyn Error>> {
+    let Some(container_path) = &arguments.add else {
+        file_path.set_extension("bro");
+        if arguments.append && file_path.exists() {
+            cs.append_to_file(&file_path)?;
+        } else {
+            std::fs::write(file_path, cs.to_bytes())?;
         }
+        return Ok(());
+    };
+    let name = match &arguments.series_name {
+        Some(name) => name.clone(),
+        None => file_path
+            .file_stem()
+            .ok_or("Input has no file name to name the series after")?
+            .to_string_lossy()
+            .into_owned(),
+    };
+    let mut container = if container_path.exists() {
+        SeriesContainer::from_bytes(&std::fs::read(container_path)?)?
+    } else {
+        SeriesContainer::new()
+    };
+    container.add(&name, cs)?;
+    std::fs::write(container_path, container.to_bytes())?;
+    Ok(())
+}
+
+/// File name for an extracted series, anything that doesn't belong in a file name is replaced by `_`
+fn series_file_stem(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Builds the metadata written along with the compressed data
+fn build_metadata(source: &Path, arguments: &Args) -> Result<Metadata, Box<dyn Error>> {
+    let mut metadata = Metadata::new();
+    metadata.metric_name = arguments.metric_name.clone();
+    metadata.unit = arguments.unit.clone();
+    metadata.sampling_interval_ms = arguments.sampling_interval;
+    metadata.source_file = source
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned());
+    for label in arguments.label.iter() {
+        let (key, value) = label
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid label, expected KEY=VALUE: {}", label))?;
+        metadata.labels.insert(key.to_string(), value.to_string());
     }
-    cs.to_bytes()
+    let options = &mut metadata.compression_options;
+    options.insert(
+        "compressor".to_string(),
+        format!("{:?}", arguments.compressor).to_lowercase(),
+    );
+    options.insert("error".to_string(), arguments.error.to_string());
+    if let Some(quantum) = arguments.residual_quantum {
+        options.insert("residual_quantum".to_string(), quantum.to_string());
+    }
+    if arguments.overlap > 0 {
+        options.insert("overlap".to_string(), arguments.overlap.to_string());
+    }
+    options.insert(
+        "compression_selection_sample_level".to_string(),
+        arguments.compression_selection_sample_level.to_string(),
+    );
+    Ok(metadata)
 }
 
 /// Compresses the data based on the provided tag and arguments.
-fn decompress_data(compressed_data: &[u8]) -> Vec<f64> {
+fn decompress_data(compressed_data: &[u8]) -> Result<Vec<f64>, Box<dyn Error>> {
     debug!("decompressing data!");
-    let cs = CompressedStream::from_bytes(compressed_data);
-    cs.decompress()
+    let cs = CompressedStream::from_bytes(compressed_data)?;
+    Ok(cs.decompress()?)
 }
 
 #[derive(Parser, Default, Debug)]
@@ -184,10 +347,68 @@ struct Args {
     #[arg(short, long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(0..51), verbatim_doc_comment )]
     error: u8,
 
+    /// Keeps what the model compressors (fft, dct, polynomial, idw, wavelet and chebyshev) miss from the samples, so every
+    /// sample is restored within half of this value of the original one. 0 makes them lossless. With auto, the smallest
+    /// model is picked for each frame.
+    #[arg(long, value_name = "QUANTUM")]
+    residual_quantum: Option<f64>,
+
+    /// Makes each frame of the lossy compressors overlap the next one by this many samples, frames are crossfaded
+    /// over the overlap on decompression so there are no steps where they meet. Not used with --residual-quantum.
+    #[arg(long, value_name = "SAMPLES", default_value_t = 0)]
+    overlap: usize,
+
     /// Uncompresses the input file/directory
     #[arg(short, action)]
     uncompress: bool,
 
+    /// Checks the input file/directory against the stored checksums, without decompressing it
+    #[arg(long, action)]
+    verify: bool,
+
+    /// Prints the metadata of the input file/directory, without decompressing it
+    #[arg(long, action)]
+    metadata: bool,
+
+    /// Appends the compressed input to the .bro file next to it, instead of replacing it. The file is created if
+    /// it doesn't exist.
+    #[arg(long, action, conflicts_with = "add")]
+    append: bool,
+
+    /// Lists the series stored in the input container
+    #[arg(long, action)]
+    list: bool,
+
+    /// Extracts a series from the input container into <NAME>.bro, next to the container
+    #[arg(long, value_name = "NAME")]
+    extract: Option<String>,
+
+    /// Adds the input file/directory to a series container, creating it if needed.
+    /// A .bro input is added as is, anything else is compressed first.
+    #[arg(long, value_name = "CONTAINER", verbatim_doc_comment)]
+    add: Option<PathBuf>,
+
+    /// Name of the series added with --add, default is the input file name without extension
+    #[arg(long, value_name = "NAME")]
+    series_name: Option<String>,
+
+    /// Name of the metric, stored in the metadata of the compressed file
+    #[arg(long)]
+    metric_name: Option<String>,
+
+    /// Label of the series, stored in the metadata of the compressed file. Can be repeated:
+    ///   --label instance=localhost:9100 --label job=node
+    #[arg(long, value_name = "KEY=VALUE", verbatim_doc_comment)]
+    label: Vec<String>,
+
+    /// Unit of the samples, stored in the metadata of the compressed file
+    #[arg(long)]
+    unit: Option<String>,
+
+    /// Time between samples in milliseconds, stored in the metadata of the compressed file
+    #[arg(long, value_name = "MS")]
+    sampling_interval: Option<u64>,
+
     /// Samples the input data instead of using all the data for selecting the optimal compressor.
     /// Only impacts speed, might or not increased compression ratio. For best results use 0 (default).
     /// Only works when compression = Auto.
@@ -225,6 +446,15 @@ enum CompressorType {
     Constant,
     Polynomial,
     Idw,
+    Gorilla,
+    Delta,
+    Counter,
+    Rle,
+    Pla,
+    Dct,
+    Wavelet,
+    Chebyshev,
+    Seasonal,
 }
 
 fn main() {
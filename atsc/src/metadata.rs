@@ -0,0 +1,191 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::compressor::BinConfig;
+use crate::error::Error;
+use crate::utils::checksum::crc32;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const METRIC_NAME: &str = "metric_name";
+const UNIT: &str = "unit";
+const SAMPLING_INTERVAL_MS: &str = "sampling_interval_ms";
+const SOURCE_FILE: &str = "source_file";
+const CREATED_AT: &str = "created_at";
+const ATSC_VERSION: &str = "atsc_version";
+/// Prefix of the keys holding the labels of the series
+const LABEL_PREFIX: &str = "label.";
+/// Prefix of the keys holding the options the stream was compressed with
+const OPTION_PREFIX: &str = "option.";
+
+/// Describes what a stream holds. Written right after the header, so it can be read without decoding any frame.
+///
+/// It is stored as a flat list of key/value pairs, keys this release doesn't know about are kept in `extra` and
+/// written back untouched.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub metric_name: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    pub unit: Option<String>,
+    /// Time between samples, in milliseconds
+    pub sampling_interval_ms: Option<u64>,
+    /// File the samples were read from
+    pub source_file: Option<String>,
+    /// Creation time, in seconds since the UNIX epoch
+    pub created_at: Option<u64>,
+    /// Version of atsc that wrote the stream
+    pub atsc_version: Option<String>,
+    /// Options used to compress the stream (compressor, error, ...)
+    pub compression_options: BTreeMap<String, String>,
+    pub extra: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// Metadata stamped with the current time and the version of this release
+    pub fn new() -> Self {
+        Metadata {
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs()),
+            atsc_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// All the key/value pairs, in the order they are stored
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let mut entries = Vec::new();
+        let mut push = |key: &str, value: Option<String>| {
+            if let Some(value) = value {
+                entries.push((key.to_string(), value));
+            }
+        };
+        push(METRIC_NAME, self.metric_name.clone());
+        push(UNIT, self.unit.clone());
+        push(
+            SAMPLING_INTERVAL_MS,
+            self.sampling_interval_ms.map(|v| v.to_string()),
+        );
+        push(SOURCE_FILE, self.source_file.clone());
+        push(CREATED_AT, self.created_at.map(|v| v.to_string()));
+        push(ATSC_VERSION, self.atsc_version.clone());
+        for (key, value) in self.labels.iter() {
+            entries.push((format!("{}{}", LABEL_PREFIX, key), value.clone()));
+        }
+        for (key, value) in self.compression_options.iter() {
+            entries.push((format!("{}{}", OPTION_PREFIX, key), value.clone()));
+        }
+        entries.extend(self.extra.iter().map(|(k, v)| (k.clone(), v.clone())));
+        entries
+    }
+
+    /// Builds the metadata back from its key/value pairs
+    pub fn from_entries(entries: Vec<(String, String)>) -> Result<Self, Error> {
+        let mut metadata = Metadata::default();
+        for (key, value) in entries {
+            match key.as_str() {
+                METRIC_NAME => metadata.metric_name = Some(value),
+                UNIT => metadata.unit = Some(value),
+                SAMPLING_INTERVAL_MS => {
+                    metadata.sampling_interval_ms = Some(parse_number(&key, &value)?)
+                }
+                SOURCE_FILE => metadata.source_file = Some(value),
+                CREATED_AT => metadata.created_at = Some(parse_number(&key, &value)?),
+                ATSC_VERSION => metadata.atsc_version = Some(value),
+                _ => {
+                    if let Some(label) = key.strip_prefix(LABEL_PREFIX) {
+                        metadata.labels.insert(label.to_string(), value);
+                    } else if let Some(option) = key.strip_prefix(OPTION_PREFIX) {
+                        metadata
+                            .compression_options
+                            .insert(option.to_string(), value);
+                    } else {
+                        metadata.extra.insert(key, value);
+                    }
+                }
+            }
+        }
+        Ok(metadata)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = BinConfig::get();
+        bincode::encode_to_vec(self.entries(), config).unwrap()
+    }
+
+    /// Reads the metadata from its bytes, checking them against `checksum`
+    pub fn from_bytes(data: &[u8], checksum: u32) -> Result<Self, Error> {
+        if crc32(data) != checksum {
+            return Err(Error::CorruptData("metadata checksum mismatch".to_string()));
+        }
+        let config = BinConfig::get();
+        let (entries, _) = bincode::decode_from_slice(data, config)?;
+        Metadata::from_entries(entries)
+    }
+}
+
+fn parse_number(key: &str, value: &str) -> Result<u64, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::CorruptData(format!("metadata {} is not a number: {}", key, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_roundtrip() {
+        let mut metadata = Metadata::new();
+        metadata.metric_name = Some("node_cpu_seconds_total".to_string());
+        metadata.unit = Some("seconds".to_string());
+        metadata.sampling_interval_ms = Some(15000);
+        metadata
+            .labels
+            .insert("instance".to_string(), "localhost:9100".to_string());
+        metadata
+            .compression_options
+            .insert("error".to_string(), "3".to_string());
+        let bytes = metadata.to_bytes();
+        let decoded = Metadata::from_bytes(&bytes, crc32(&bytes)).unwrap();
+        assert_eq!(decoded, metadata);
+        assert_eq!(
+            decoded.atsc_version.as_deref(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+    }
+
+    #[test]
+    fn test_unknown_keys_kept() {
+        let entries = vec![
+            ("unit".to_string(), "bytes".to_string()),
+            ("owner".to_string(), "storage-team".to_string()),
+        ];
+        let metadata = Metadata::from_entries(entries.clone()).unwrap();
+        assert_eq!(metadata.unit.as_deref(), Some("bytes"));
+        assert_eq!(metadata.extra["owner"], "storage-team");
+        assert_eq!(metadata.entries(), entries);
+    }
+
+    #[test]
+    fn test_bad_metadata_rejected() {
+        let entries = vec![("created_at".to_string(), "yesterday".to_string())];
+        assert!(Metadata::from_entries(entries).is_err());
+        let bytes = Metadata::new().to_bytes();
+        assert!(Metadata::from_bytes(&bytes, crc32(&bytes) ^ 1).is_err());
+    }
+}
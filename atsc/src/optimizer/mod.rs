@@ -43,13 +43,14 @@ pub struct OptimizerPlan {
 }
 
 impl OptimizerPlan {
-    /// Creates an optimal data compression plan
+    /// Creates an optimal data compression plan.
+    /// NaN and infinite samples are kept in place, so every sample stays aligned with its timestamp, the frames
+    /// record them and fill them in before compressing.
     pub fn plan(data: &[f64]) -> Self {
-        let c_data = OptimizerPlan::clean_data(data);
-        let chunks = OptimizerPlan::get_chunks_sizes(c_data.len());
-        let optimizer = OptimizerPlan::assign_compressor(&c_data, &chunks, None);
+        let chunks = OptimizerPlan::get_chunks_sizes(data.len());
+        let optimizer = OptimizerPlan::assign_compressor(data, &chunks, None);
         OptimizerPlan {
-            data: c_data,
+            data: data.to_vec(),
             chunk_sizes: chunks,
             compressors: optimizer,
         }
@@ -60,16 +61,6 @@ impl OptimizerPlan {
         self.compressors = new_compressors;
     }
 
-    /// Removes NaN and infinite references from the data
-    pub fn clean_data(wav_data: &[f64]) -> Vec<f64> {
-        // Cleaning data, removing NaN, etc. This might reduce sample count
-        wav_data
-            .iter()
-            .filter(|x| !(x.is_nan() || x.is_infinite()))
-            .copied()
-            .collect()
-    }
-
     /// This function gets a length and returns a vector with the chunk sizes to feed to the different compressors
     /// A lot of assumptions go into selecting the chunk size, including:
     /// 1. Collection rate - It is not expected that the collection rate exceeds 1point sec (it is expected actually less)
@@ -108,6 +99,26 @@ impl OptimizerPlan {
         output
     }
 
+    /// Returns a vector with the data slice, extended with up to `overlap` samples of the next chunk, the number of
+    /// samples it was extended with and the compressor associated
+    pub fn get_lapped_execution(&self, overlap: usize) -> Vec<(&Compressor, &[f64], usize)> {
+        let mut output = Vec::with_capacity(self.chunk_sizes.len());
+        let mut s = 0;
+        for (i, size) in self.chunk_sizes.iter().enumerate() {
+            let tail = self
+                .chunk_sizes
+                .get(i + 1)
+                .map_or(0, |next| overlap.min(*next));
+            output.push((
+                &self.compressors[i],
+                &self.data[s..(s + *size + tail)],
+                tail,
+            ));
+            s += *size;
+        }
+        output
+    }
+
     /// Walks the data, checks how much variability is in the data, and assigns a compressor based on that
     fn get_compressor(data: &[f64]) -> Compressor {
         let _ = data.iter().map(|&f| f64_to_u64(f, 0));
@@ -146,6 +157,28 @@ mod tests {
         assert_eq!(plan_vec.len(), 2);
     }
 
+    #[test]
+    fn optimizer_lapped() {
+        let fake_data: Vec<f64> = (0..2049).map(|i| i as f64).collect();
+        let op = OptimizerPlan::plan(&fake_data);
+        let plan_vec = op.get_lapped_execution(64);
+        assert_eq!(plan_vec.len(), 2);
+        assert_eq!(plan_vec[0].1.len(), 2048 + 1);
+        assert_eq!(plan_vec[0].2, 1);
+        assert_eq!(plan_vec[1].1, &[2048.0]);
+        assert_eq!(plan_vec[1].2, 0);
+    }
+
+    #[test]
+    fn optimizer_keeps_non_finite_samples() {
+        let mut fake_data = vec![12.23; 1024];
+        fake_data[10] = f64::NAN;
+        fake_data[20] = f64::INFINITY;
+        let op = OptimizerPlan::plan(&fake_data);
+        assert_eq!(op.data.len(), 1024);
+        assert!(op.data[10].is_nan());
+    }
+
     #[test]
     fn test_get_chunks_sizes() {
         let len_very_large: usize = 131072 * 3 + 1765;
@@ -0,0 +1,144 @@
+/*
+Copyright 2024 NetApp, Inc.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    https://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use crate::error::Error;
+
+/// Writes values of any width up to 64 bits, most significant bit first
+#[derive(Debug, Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits used in the last byte, 0 means it is full (or there is none)
+    used: u32,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        BitWriter::default()
+    }
+
+    pub fn write_bit(&mut self, bit: bool) {
+        self.write_bits(bit as u64, 1);
+    }
+
+    /// Writes the lowest `count` bits of `value`
+    pub fn write_bits(&mut self, value: u64, mut count: u32) {
+        debug_assert!(count <= 64);
+        while count > 0 {
+            if self.used == 0 {
+                self.bytes.push(0);
+            }
+            let free = 8 - self.used;
+            let take = free.min(count);
+            let chunk = ((value >> (count - take)) & ((1 << take) - 1)) as u8;
+            *self.bytes.last_mut().unwrap() |= chunk << (free - take);
+            self.used = (self.used + take) % 8;
+            count -= take;
+        }
+    }
+
+    /// Returns the written bytes, the last one padded with zeros
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Reads the values written by a `BitWriter`
+#[derive(Debug)]
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    /// Position in bits
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader { data, position: 0 }
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool, Error> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    /// Reads `count` bits, failing if the data ends before
+    pub fn read_bits(&mut self, mut count: u32) -> Result<u64, Error> {
+        debug_assert!(count <= 64);
+        if self.position + count as usize > self.data.len() * 8 {
+            return Err(Error::TruncatedInput);
+        }
+        let mut value: u64 = 0;
+        while count > 0 {
+            let used = (self.position % 8) as u32;
+            let take = (8 - used).min(count);
+            let byte = self.data[self.position / 8] as u64;
+            let chunk = (byte >> (8 - used - take)) & ((1 << take) - 1);
+            value = (value << take) | chunk;
+            self.position += take as usize;
+            count -= take;
+        }
+        Ok(value)
+    }
+}
+
+/// Maps signed values to unsigned ones, small values of either sign stay small: 0, -1, 1, -2... become 0, 1, 2, 3...
+pub fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+/// Bits needed to write `value`, 0 for 0
+pub fn bit_width(value: u64) -> u32 {
+    64 - value.leading_zeros()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits_roundtrip() {
+        let mut writer = BitWriter::new();
+        writer.write_bit(true);
+        writer.write_bits(0b101, 3);
+        writer.write_bits(u64::MAX, 64);
+        writer.write_bits(0x1234, 13);
+        let bytes = writer.finish();
+        assert_eq!(bytes.len(), 11);
+        assert_eq!(bytes[0], 0b1101_1111);
+        let mut reader = BitReader::new(&bytes);
+        assert!(reader.read_bit().unwrap());
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        assert_eq!(reader.read_bits(64).unwrap(), u64::MAX);
+        assert_eq!(reader.read_bits(13).unwrap(), 0x1234);
+        assert!(matches!(reader.read_bits(8), Err(Error::TruncatedInput)));
+    }
+
+    #[test]
+    fn test_zigzag() {
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+        assert_eq!(zigzag(i64::MIN), u64::MAX);
+        for value in [0, 5, -5, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(value)), value);
+        }
+        assert_eq!(bit_width(0), 0);
+        assert_eq!(bit_width(5), 3);
+        assert_eq!(bit_width(u64::MAX), 64);
+    }
+}
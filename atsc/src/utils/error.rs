@@ -115,6 +115,29 @@ pub fn error_mape(original: &[f64], generated: &[f64]) -> f64 {
     abs_error / original.len() as f64
 }
 
+/// Same as `error_mape`, but samples matched exactly count as no error, so zeros that are restored exactly don't
+/// turn the error into NaN
+/// # Panics:
+/// When the 2 arrays don't have the same size
+pub fn error_mape_exact_zeros(original: &[f64], generated: &[f64]) -> f64 {
+    if original.len() != generated.len() {
+        panic!("Can't compute error! Arrays with different lenghts.")
+    }
+
+    let abs_error: f64 = original
+        .iter()
+        .zip(generated.iter())
+        .map(|(original, generated)| {
+            if original == generated {
+                0.0
+            } else {
+                ((generated - original) / original).abs()
+            }
+        })
+        .sum();
+    abs_error / original.len() as f64
+}
+
 /// Computes the Symmetric Mean Absolute Percentage Error between 2 signals
 /// # Panics:
 /// When the 2 arrays don't have the same size
@@ -14,6 +14,8 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+pub mod bits;
+pub mod checksum;
 pub mod error;
 pub mod readers;
 pub mod writers;
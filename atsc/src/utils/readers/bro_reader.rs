@@ -36,7 +36,7 @@ pub fn read_file(file_path: &Path) -> Result<Option<Vec<u8>>, Error> {
         Ok(None)
     }
 }
-fn is_bro_file(file_path: &Path) -> io::Result<bool> {
+pub fn is_bro_file(file_path: &Path) -> io::Result<bool> {
     // Open the file for reading and read the first 12 bytes (header) of the file
     let mut file = fs::File::open(file_path)?;
     let mut header = [0u8; 12];
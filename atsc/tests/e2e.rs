@@ -33,11 +33,56 @@ fn test_compressor_noop() {
     test_lossless_compression("noop")
 }
 
+#[test]
+fn test_compressor_gorilla() {
+    test_lossless_compression("gorilla")
+}
+
+#[test]
+fn test_compressor_delta() {
+    test_lossless_compression("delta")
+}
+
+#[test]
+fn test_compressor_counter() {
+    test_lossless_compression("counter")
+}
+
+#[test]
+fn test_compressor_rle() {
+    test_lossless_compression("rle")
+}
+
+#[test]
+fn test_compressor_pla_lossy() {
+    test_lossy_compression("pla")
+}
+
 #[test]
 fn test_compressor_fft_lossy() {
     test_lossy_compression("fft")
 }
 
+#[test]
+fn test_compressor_dct_lossy() {
+    test_lossy_compression("dct")
+}
+
+#[test]
+fn test_compressor_wavelet_lossy() {
+    test_lossy_compression("wavelet")
+}
+
+#[test]
+fn test_compressor_chebyshev_lossy() {
+    test_lossy_compression("chebyshev")
+}
+
+#[test]
+fn test_compressor_seasonal_lossy() {
+    test_lossy_compression("seasonal")
+}
+
 #[test]
 fn test_compressor_auto_lossless() {
     test_lossless_compression("auto")
@@ -178,6 +223,192 @@ fn test_compressor_constant() {
     )
 }
 
+#[test]
+fn test_residuals() {
+    let test_dir = prepare_test_dir();
+    run_compressor(&[
+        "--compressor",
+        "fft",
+        "--residual-quantum",
+        "0",
+        test_dir.join(TEST_FILE_NAME).to_str().unwrap(),
+    ]);
+    run_compressor(&[
+        "-u",
+        test_dir.join(TEST_COMPRESSED_FILE_NAME).to_str().unwrap(),
+    ]);
+    compare_samples_lossless(
+        &PathBuf::from(TEST_WBRO_PATH),
+        &test_dir.join(TEST_FILE_NAME),
+    )
+}
+
+#[test]
+fn test_residuals_auto() {
+    let test_dir = prepare_test_dir();
+    run_compressor(&[
+        "--residual-quantum",
+        "0",
+        test_dir.join(TEST_FILE_NAME).to_str().unwrap(),
+    ]);
+    run_compressor(&[
+        "-u",
+        test_dir.join(TEST_COMPRESSED_FILE_NAME).to_str().unwrap(),
+    ]);
+    compare_samples_lossless(
+        &PathBuf::from(TEST_WBRO_PATH),
+        &test_dir.join(TEST_FILE_NAME),
+    )
+}
+
+#[test]
+fn test_overlap() {
+    let test_dir = prepare_test_dir();
+    run_compressor(&[
+        "--compressor",
+        "fft",
+        "--overlap",
+        "64",
+        test_dir.join(TEST_FILE_NAME).to_str().unwrap(),
+    ]);
+    run_compressor(&[
+        "-u",
+        test_dir.join(TEST_COMPRESSED_FILE_NAME).to_str().unwrap(),
+    ]);
+    compare_samples_with_allowed_error(
+        &PathBuf::from(TEST_WBRO_PATH),
+        &test_dir.join(TEST_FILE_NAME),
+    )
+}
+
+#[test]
+fn test_verify() {
+    let test_dir = prepare_test_dir();
+    run_compressor(&[test_dir.join(TEST_FILE_NAME).to_str().unwrap()]);
+    run_compressor(&[
+        "--verify",
+        test_dir.join(TEST_COMPRESSED_FILE_NAME).to_str().unwrap(),
+    ]);
+}
+
+#[test]
+fn test_metadata() {
+    let test_dir = prepare_test_dir();
+    run_compressor(&[
+        "--metric-name",
+        "go_gc_duration_count",
+        "--label",
+        "job=node",
+        "--unit",
+        "seconds",
+        test_dir.join(TEST_FILE_NAME).to_str().unwrap(),
+    ]);
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_atsc"))
+        .args([
+            "--metadata",
+            test_dir.join(TEST_COMPRESSED_FILE_NAME).to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("metric_name=go_gc_duration_count"));
+    assert!(stdout.contains("label.job=node"));
+    assert!(stdout.contains("unit=seconds"));
+    assert!(stdout.contains(&format!("source_file={}", TEST_FILE_NAME)));
+}
+
+#[test]
+fn test_series_container() {
+    let test_dir = prepare_test_dir();
+    let container = test_dir.join("series.brs");
+    let input = test_dir.join(TEST_FILE_NAME);
+    // Compressed straight into the container
+    run_compressor(&[
+        "--add",
+        container.to_str().unwrap(),
+        "--compressor",
+        "noop",
+        input.to_str().unwrap(),
+    ]);
+    // An existing .bro is added as is
+    run_compressor(&[input.to_str().unwrap()]);
+    let compressed = test_dir.join(TEST_COMPRESSED_FILE_NAME);
+    run_compressor(&[
+        "--add",
+        container.to_str().unwrap(),
+        "--series-name",
+        "copy",
+        compressed.to_str().unwrap(),
+    ]);
+    // Names must be unique
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_atsc"))
+        .args([
+            "--add",
+            container.to_str().unwrap(),
+            "--series-name",
+            "copy",
+            compressed.to_str().unwrap(),
+        ])
+        .status()
+        .unwrap();
+    assert!(!status.success());
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_atsc"))
+        .args(["--list", container.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 2);
+    assert!(stdout.starts_with("go_gc_heap_goal_bytes\t"));
+    assert!(stdout.contains("\ncopy\t"));
+
+    run_compressor(&["--extract", "copy", container.to_str().unwrap()]);
+    run_compressor(&["-u", test_dir.join("copy.bro").to_str().unwrap()]);
+    compare_samples_with_allowed_error(&PathBuf::from(TEST_WBRO_PATH), &test_dir.join("copy.wbro"));
+}
+
+#[test]
+fn test_append() {
+    let test_dir = prepare_test_dir();
+    let input = test_dir.join(TEST_FILE_NAME);
+    run_compressor(&["--compressor", "noop", input.to_str().unwrap()]);
+    run_compressor(&["--append", "--compressor", "noop", input.to_str().unwrap()]);
+    run_compressor(&[
+        "--verify",
+        test_dir.join(TEST_COMPRESSED_FILE_NAME).to_str().unwrap(),
+    ]);
+    run_compressor(&[
+        "-u",
+        test_dir.join(TEST_COMPRESSED_FILE_NAME).to_str().unwrap(),
+    ]);
+    // The samples are there twice
+    let original = WavBrro::from_file(&PathBuf::from(TEST_WBRO_PATH)).unwrap();
+    let uncompressed = WavBrro::from_file(&input).unwrap();
+    assert_eq!(uncompressed, [original.clone(), original].concat());
+}
+
+#[test]
+fn test_missing_samples() {
+    let test_dir = tempfile::tempdir().unwrap().into_path();
+    let input = test_dir.join(TEST_FILE_NAME);
+    let mut samples = WavBrro::from_file(&PathBuf::from(TEST_WBRO_PATH)).unwrap();
+    samples[3] = f64::NAN;
+    samples[10..20].fill(f64::NAN);
+    samples[30] = f64::INFINITY;
+    WavBrro::to_file_with_data(&input, &samples);
+    run_compressor(&["--compressor", "noop", input.to_str().unwrap()]);
+    run_compressor(&[
+        "-u",
+        test_dir.join(TEST_COMPRESSED_FILE_NAME).to_str().unwrap(),
+    ]);
+    // Every sample is back in its place
+    let uncompressed = WavBrro::from_file(&input).unwrap();
+    let bits = |d: &[f64]| d.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+    assert_eq!(bits(&uncompressed), bits(&samples));
+}
+
 /// Runs compression and decompression test for a specified compressor.
 /// max_error is an error level, compression speed is set as the lowest (0).
 ///
@@ -89,6 +89,15 @@ enum CompressorType {
     Constant,
     Polynomial,
     Idw,
+    Gorilla,
+    Delta,
+    Counter,
+    Rle,
+    Pla,
+    Dct,
+    Wavelet,
+    Chebyshev,
+    Seasonal,
 }
 
 fn compress_data(vec: &[f64], arguments: &Args) -> Vec<u8> {
@@ -104,6 +113,15 @@ fn compress_data(vec: &[f64], arguments: &Args) -> Vec<u8> {
         CompressorType::Fft => op.set_compressor(Compressor::FFT),
         CompressorType::Polynomial => op.set_compressor(Compressor::Polynomial),
         CompressorType::Idw => op.set_compressor(Compressor::Idw),
+        CompressorType::Gorilla => op.set_compressor(Compressor::Gorilla),
+        CompressorType::Delta => op.set_compressor(Compressor::Delta),
+        CompressorType::Counter => op.set_compressor(Compressor::Counter),
+        CompressorType::Rle => op.set_compressor(Compressor::Rle),
+        CompressorType::Pla => op.set_compressor(Compressor::Pla),
+        CompressorType::Dct => op.set_compressor(Compressor::Dct),
+        CompressorType::Wavelet => op.set_compressor(Compressor::Wavelet),
+        CompressorType::Chebyshev => op.set_compressor(Compressor::Chebyshev),
+        CompressorType::Seasonal => op.set_compressor(Compressor::Seasonal),
         CompressorType::Auto => op.set_compressor(Compressor::Auto),
     }
     for (cpr, data) in op.get_execution().into_iter() {
@@ -113,6 +131,11 @@ fn compress_data(vec: &[f64], arguments: &Args) -> Vec<u8> {
             CompressorType::Fft
             | CompressorType::Polynomial
             | CompressorType::Idw
+            | CompressorType::Pla
+            | CompressorType::Dct
+            | CompressorType::Wavelet
+            | CompressorType::Chebyshev
+            | CompressorType::Seasonal
             | CompressorType::Auto => cs.compress_chunk_bounded_with(
                 data,
                 cpr.to_owned(),
@@ -121,6 +144,7 @@ fn compress_data(vec: &[f64], arguments: &Args) -> Vec<u8> {
             ),
             _ => cs.compress_chunk_with(data, cpr.to_owned()),
         }
+        .expect("failed to compress chunk");
     }
     cs.to_bytes()
 }
@@ -128,8 +152,8 @@ fn compress_data(vec: &[f64], arguments: &Args) -> Vec<u8> {
 /// Compresses the data based on the provided tag and arguments.
 fn decompress_data(compressed_data: &[u8]) -> Vec<f64> {
     debug!("decompressing data!");
-    let cs = CompressedStream::from_bytes(compressed_data);
-    cs.decompress()
+    let cs = CompressedStream::from_bytes(compressed_data).expect("failed to decode bro file");
+    cs.decompress().expect("failed to decompress bro file")
 }
 
 /// process_csv opens and parses the content of file at path